@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// A cell kind as drawn in both output formats.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Swatch {
+    pub color: [u8; 3],
+    pub glyph: char,
+}
+
+// Frames of cell kinds, each an index into the palette, that can be written
+// as plain-text frames or as an animated GIF.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    width: usize,
+    height: usize,
+    palette: Vec<Swatch>,
+    frames: Vec<Vec<u8>>,
+    delay: u16,
+}
+
+impl Animation {
+    pub fn new(width: usize, height: usize, palette: Vec<Swatch>) -> Self {
+        assert!(
+            (1..=256).contains(&palette.len()),
+            "palette must have between 1 and 256 entries"
+        );
+        assert!(width <= u16::MAX as usize && height <= u16::MAX as usize);
+        Self {
+            width,
+            height,
+            palette,
+            frames: Vec::new(),
+            delay: 5,
+        }
+    }
+
+    // Delay between frames in hundredths of a second.
+    pub fn delay(mut self, delay: u16) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub fn push(&mut self, frame: Vec<u8>) {
+        assert_eq!(frame.len(), self.width * self.height, "frame size mismatch");
+        self.frames.push(frame);
+    }
+
+    pub fn frames(&self) -> &[Vec<u8>] {
+        &self.frames
+    }
+
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        for (step, frame) in self.frames.iter().enumerate() {
+            writeln!(out, "Step {}:", step)?;
+            for row in frame.chunks(self.width.max(1)) {
+                let line: String = row
+                    .iter()
+                    .map(|&kind| self.palette[kind as usize].glyph)
+                    .collect();
+                writeln!(out, "{}", line)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_gif(&self, out: &mut impl Write) -> io::Result<()> {
+        let bits = (self.palette.len().next_power_of_two().trailing_zeros() as u8).max(1);
+        let (width, height) = (self.width as u16, self.height as u16);
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&width.to_le_bytes())?;
+        out.write_all(&height.to_le_bytes())?;
+        out.write_all(&[0x80 | (bits - 1), 0, 0])?;
+        for i in 0..1 << bits {
+            let color = self.palette.get(i).map_or([0; 3], |swatch| swatch.color);
+            out.write_all(&color)?;
+        }
+
+        // Loop forever.
+        out.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        for frame in &self.frames {
+            out.write_all(&[0x21, 0xf9, 0x04, 0x00])?;
+            out.write_all(&self.delay.to_le_bytes())?;
+            out.write_all(&[0x00, 0x00])?;
+
+            out.write_all(&[0x2c, 0, 0, 0, 0])?;
+            out.write_all(&width.to_le_bytes())?;
+            out.write_all(&height.to_le_bytes())?;
+            out.write_all(&[0x00])?;
+
+            let min_code_size = bits.max(2);
+            out.write_all(&[min_code_size])?;
+            for block in lzw(frame, min_code_size).chunks(255) {
+                out.write_all(&[block.len() as u8])?;
+                out.write_all(block)?;
+            }
+            out.write_all(&[0x00])?;
+        }
+
+        out.write_all(&[0x3b])
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+// Variable width LZW as used by GIF, starting over with a clear code once
+// the 12-bit code space is exhausted.
+fn lzw(pixels: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut writer = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        len: 0,
+    };
+
+    let mut size = min_code_size + 1;
+    let mut next = end + 1;
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    writer.write(clear, size);
+
+    let mut pixels = pixels.iter();
+    let mut current = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            writer.write(end, size);
+            return writer.finish();
+        }
+    };
+    for &pixel in pixels {
+        if let Some(&code) = dictionary.get(&(current, pixel)) {
+            current = code;
+            continue;
+        }
+
+        writer.write(current, size);
+        if next == 4096 {
+            writer.write(clear, size);
+            dictionary.clear();
+            size = min_code_size + 1;
+            next = end + 1;
+        } else {
+            dictionary.insert((current, pixel), next);
+            if next == 1 << size && size < 12 {
+                size += 1;
+            }
+            next += 1;
+        }
+        current = pixel as u16;
+    }
+    writer.write(current, size);
+    writer.write(end, size);
+    writer.finish()
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Steps until the first repeated state, `tail` being the amount of steps
+// before entering the cycle and `period` its length.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    pub tail: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    fn index(&self, n: usize) -> usize {
+        if n < self.tail {
+            n
+        } else {
+            self.tail + (n - self.tail) % self.period
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FastForward<S> {
+    pub state: S,
+    pub cycle: Option<Cycle>,
+}
+
+// Simulates `n` steps, remembering every state along with its step so the
+// result can be picked from them as soon as a state repeats.
+pub fn fast_forward<S, F>(state: S, mut step: F, n: usize) -> FastForward<S>
+where
+    S: Clone + Hash + Eq,
+    F: FnMut(&S) -> S,
+{
+    let mut seen = HashMap::from([(state.clone(), 0)]);
+    let mut state = state;
+    for i in 1..=n {
+        let next = step(&state);
+        if let Some(&tail) = seen.get(&next) {
+            let cycle = Cycle {
+                tail,
+                period: i - tail,
+            };
+            let target = cycle.index(n);
+            let (state, _) = seen.into_iter().find(|&(_, i)| i == target).unwrap();
+            return FastForward {
+                state,
+                cycle: Some(cycle),
+            };
+        }
+        seen.insert(next.clone(), i);
+        state = next;
+    }
+    FastForward { state, cycle: None }
+}
+
+// Same as `fast_forward`, but only keeps a couple of states around by using
+// Brent's algorithm, at the cost of re-running up to `tail + period` steps.
+pub fn fast_forward_brent<S, F>(state: S, mut step: F, n: usize) -> FastForward<S>
+where
+    S: Clone + Eq,
+    F: FnMut(&S) -> S,
+{
+    let simulate = |step: &mut F, mut state: S, n: usize| {
+        for _ in 0..n {
+            state = step(&state);
+        }
+        state
+    };
+
+    if n == 0 {
+        return FastForward { state, cycle: None };
+    }
+
+    // Find the period by letting the hare run ahead of a tortoise that
+    // teleports to it at every power of two.
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = state.clone();
+    let mut hare = step(&state);
+    let mut steps = 1;
+    while tortoise != hare {
+        if steps == n {
+            return FastForward {
+                state: hare,
+                cycle: None,
+            };
+        }
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = step(&hare);
+        period += 1;
+        steps += 1;
+    }
+
+    // With the hare a period ahead, both meet at the start of the cycle.
+    let mut tail = 0;
+    let mut tortoise = state.clone();
+    let mut hare = simulate(&mut step, state.clone(), period);
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        tail += 1;
+    }
+
+    let cycle = Cycle { tail, period };
+    FastForward {
+        state: simulate(&mut step, state, cycle.index(n)),
+        cycle: Some(cycle),
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 #[derive(Debug)]
 pub struct Input {
     games: Vec<Game>,
@@ -10,8 +12,22 @@ impl From<&str> for Input {
     }
 }
 
+impl Input {
+    pub fn games(&self) -> &[Game] {
+        &self.games
+    }
+
+    pub fn possible_games<'a>(&'a self, bag: &'a Cubes) -> impl Iterator<Item = &'a Game> + 'a {
+        self.games.iter().filter(move |game| game.is_playable(bag))
+    }
+
+    pub fn minimal_bags(&self) -> impl Iterator<Item = (usize, Cubes)> + '_ {
+        self.games.iter().map(|game| (game.n, game.minimal_bag()))
+    }
+}
+
 #[derive(Debug)]
-struct Game {
+pub struct Game {
     n: usize,
     cubes: Vec<Cubes>,
 }
@@ -27,26 +43,49 @@ impl From<&str> for Game {
 }
 
 impl Game {
-    fn is_playable(&self, cubes: &Cubes) -> bool {
-        self.cubes.iter().all(|c| c.is_playable(cubes))
+    pub fn id(&self) -> usize {
+        self.n
+    }
+
+    pub fn is_playable(&self, bag: &Cubes) -> bool {
+        self.cubes.iter().all(|c| c.is_playable(bag))
     }
 
-    fn power_set(&self) -> usize {
+    pub fn minimal_bag(&self) -> Cubes {
         let mut minimal_cubes = Cubes::default();
         for c in &self.cubes {
-            minimal_cubes.red = minimal_cubes.red.max(c.red);
-            minimal_cubes.green = minimal_cubes.green.max(c.green);
-            minimal_cubes.blue = minimal_cubes.blue.max(c.blue);
+            for (color, &n) in &c.colors {
+                let max = minimal_cubes.colors.entry(color.clone()).or_default();
+                *max = (*max).max(n);
+            }
         }
-        minimal_cubes.red * minimal_cubes.green * minimal_cubes.blue
+        minimal_cubes
+    }
+
+    pub fn limiting_colors(&self, bag: &Cubes) -> Vec<&str> {
+        let mut colors: Vec<_> = self
+            .cubes
+            .iter()
+            .flat_map(|c| c.colors.iter())
+            .filter(|(color, &n)| n > bag.get(color))
+            .map(|(color, _)| color.as_str())
+            .collect();
+        colors.sort();
+        colors.dedup();
+        colors
+    }
+
+    // A color among `colors` that the game never draws needs no cubes, so it
+    // makes the power zero.
+    pub fn power_set(&self, colors: &[&str]) -> usize {
+        let bag = self.minimal_bag();
+        colors.iter().map(|color| bag.get(color)).product()
     }
 }
 
-#[derive(Debug, Default)]
-struct Cubes {
-    red: usize,
-    green: usize,
-    blue: usize,
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Cubes {
+    colors: BTreeMap<String, usize>,
 }
 
 impl From<&str> for Cubes {
@@ -55,43 +94,44 @@ impl From<&str> for Cubes {
         for ncolor in s.split(", ") {
             let (n, color) = ncolor.split_once(' ').unwrap();
             let n = n.parse().unwrap();
-            match color {
-                "red" => cube.red = n,
-                "green" => cube.green = n,
-                "blue" => cube.blue = n,
-                _ => panic!("invalid color: {}", color),
-            }
+            cube.colors.insert(color.to_string(), n);
         }
         cube
     }
 }
 
 impl Cubes {
-    fn is_playable(&self, other: &Cubes) -> bool {
-        self.red <= other.red && self.green <= other.green && self.blue <= other.blue
+    pub fn get(&self, color: &str) -> usize {
+        self.colors.get(color).copied().unwrap_or(0)
+    }
+
+    pub fn colors(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.colors.iter().map(|(color, &n)| (color.as_str(), n))
+    }
+
+    pub fn is_playable(&self, other: &Cubes) -> bool {
+        self.colors.iter().all(|(color, &n)| n <= other.get(color))
     }
 }
 
 pub fn part1(input: &Input) -> usize {
-    const CUBES: Cubes = Cubes {
-        red: 12,
-        green: 13,
-        blue: 14,
-    };
-
-    input
-        .games
-        .iter()
-        .filter_map(|game| {
-            if game.is_playable(&CUBES) {
-                Some(game.n)
-            } else {
-                None
-            }
-        })
-        .sum()
+    let bag = Cubes::from("12 red, 13 green, 14 blue");
+    input.possible_games(&bag).map(Game::id).sum()
 }
 
 pub fn part2(input: &Input) -> usize {
-    input.games.iter().map(Game::power_set).sum()
+    let colors = ["red", "green", "blue"];
+    input.games.iter().map(|game| game.power_set(&colors)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn power_set_of_game_missing_a_color() {
+        let game = Game::from("Game 1: 3 blue, 4 red");
+        assert_eq!(game.power_set(&["red", "green", "blue"]), 0);
+        assert_eq!(game.power_set(&["red", "blue"]), 12);
+    }
 }
@@ -1,14 +1,19 @@
-use std::collections::{HashMap, HashSet};
-
 #[derive(Debug)]
 pub struct Input {
-    map: Vec<Vec<u8>>,
+    schematic: Schematic,
 }
 
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
-        let map = s.lines().map(|line| line.as_bytes().to_vec()).collect();
-        Self { map }
+        let map: Vec<_> = s.lines().map(|line| line.as_bytes().to_vec()).collect();
+        let schematic = Schematic::new(&map);
+        Self { schematic }
+    }
+}
+
+impl Input {
+    pub fn schematic(&self) -> &Schematic {
+        &self.schematic
     }
 }
 
@@ -57,71 +62,137 @@ impl<'b> Iterator for NumberPartIter<'b> {
     }
 }
 
-pub fn part1(input: &Input) -> usize {
-    let ymax = input.map.len();
-    let xmax = input.map[0].len();
-
-    let mut symbols = HashSet::new();
-    for (y, row) in input.map.iter().enumerate() {
-        for (x, &symbol) in row.iter().enumerate() {
-            if symbol != b'.' && symbol.is_ascii_digit() {
-                symbols.insert((y, x));
+#[derive(Debug)]
+pub struct Symbol {
+    pub kind: u8,
+    pub pos: (usize, usize),
+    parts: Vec<usize>,
+}
+
+#[derive(Debug)]
+pub struct Number {
+    pub value: usize,
+    pub row: usize,
+    pub cols: (usize, usize),
+    symbols: Vec<usize>,
+}
+
+// Bipartite graph between symbols and the numbers adjacent to them, with the
+// edges stored as indices on both sides.
+#[derive(Debug)]
+pub struct Schematic {
+    symbols: Vec<Symbol>,
+    numbers: Vec<Number>,
+}
+
+impl Schematic {
+    fn new(map: &[Vec<u8>]) -> Self {
+        // Rows may be ragged, so the table is as wide as the widest of them.
+        let ymax = map.len();
+        let xmax = map.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut symbols = Vec::new();
+        let mut lookup = vec![vec![None; xmax]; ymax];
+        for (y, row) in map.iter().enumerate() {
+            for (x, &kind) in row.iter().enumerate() {
+                if kind != b'.' && !kind.is_ascii_digit() && !kind.is_ascii_whitespace() {
+                    lookup[y][x] = Some(symbols.len());
+                    symbols.push(Symbol {
+                        kind,
+                        pos: (y, x),
+                        parts: Vec::new(),
+                    });
+                }
             }
         }
-    }
 
-    let mut summa = 0;
-    for (y, row) in input.map.iter().enumerate() {
-        for (coords, number) in NumberPartIter::new(row) {
-            let ystart = y.saturating_sub(1);
-            let yend = (y + 1).min(ymax - 1);
-            let xstart = coords.0.saturating_sub(1);
-            let xend = (coords.1 + 1).min(xmax - 1);
-            for yi in ystart..=yend {
-                for xi in xstart..=xend {
-                    if symbols.contains(&(yi, xi)) {
-                        summa += number;
+        let mut numbers = Vec::new();
+        for (y, row) in map.iter().enumerate() {
+            for (cols, value) in NumberPartIter::new(row) {
+                let id = numbers.len();
+                let mut adjacent = Vec::new();
+                let ystart = y.saturating_sub(1);
+                let yend = (y + 1).min(ymax - 1);
+                let xstart = cols.0.saturating_sub(1);
+                let xend = (cols.1 + 1).min(xmax - 1);
+                for line in &lookup[ystart..=yend] {
+                    for symbol in line[xstart..=xend].iter().flatten() {
+                        symbols[*symbol].parts.push(id);
+                        adjacent.push(*symbol);
                     }
                 }
+                numbers.push(Number {
+                    value,
+                    row: y,
+                    cols,
+                    symbols: adjacent,
+                });
             }
         }
+
+        Self { symbols, numbers }
     }
 
-    summa
-}
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
 
-pub fn part2(input: &Input) -> usize {
-    let ymax = input.map.len();
-    let xmax = input.map[0].len();
-
-    let mut gears = HashMap::new();
-    for (y, row) in input.map.iter().enumerate() {
-        for (x, &symbol) in row.iter().enumerate() {
-            if symbol == b'*' {
-                gears.insert((y, x), Vec::new());
-            }
-        }
+    pub fn numbers(&self) -> &[Number] {
+        &self.numbers
     }
 
-    for (y, row) in input.map.iter().enumerate() {
-        for (coords, number) in NumberPartIter::new(row) {
-            let ystart = y.saturating_sub(1);
-            let yend = (y + 1).min(ymax - 1);
-            let xstart = coords.0.saturating_sub(1);
-            let xend = (coords.1 + 1).min(xmax - 1);
-            for yi in ystart..=yend {
-                for xi in xstart..=xend {
-                    if gears.contains_key(&(yi, xi)) {
-                        gears.get_mut(&(yi, xi)).unwrap().push(number);
-                    }
-                }
-            }
-        }
+    pub fn parts_of<'a>(&'a self, symbol: &'a Symbol) -> impl Iterator<Item = &'a Number> + 'a {
+        symbol.parts.iter().map(|&i| &self.numbers[i])
+    }
+
+    pub fn symbols_of<'a>(&'a self, number: &'a Number) -> impl Iterator<Item = &'a Symbol> + 'a {
+        number.symbols.iter().map(|&i| &self.symbols[i])
+    }
+
+    pub fn parts(&self) -> impl Iterator<Item = &Number> {
+        self.numbers
+            .iter()
+            .filter(|number| !number.symbols.is_empty())
+    }
+
+    pub fn parts_touching(&self, kind: u8) -> impl Iterator<Item = &Number> {
+        self.numbers
+            .iter()
+            .filter(move |number| number.symbols.iter().any(|&i| self.symbols[i].kind == kind))
     }
 
-    gears
-        .values()
-        .filter(|numbers| numbers.len() == 2)
-        .map(|numbers| numbers[0] * numbers[1])
+    pub fn gears(&self, kind: u8, n: usize) -> impl Iterator<Item = &Symbol> {
+        self.symbols
+            .iter()
+            .filter(move |symbol| symbol.kind == kind && symbol.parts.len() == n)
+    }
+
+    pub fn isolated(&self) -> impl Iterator<Item = &Number> {
+        self.numbers
+            .iter()
+            .filter(|number| number.symbols.is_empty())
+    }
+
+    pub fn shared(&self) -> impl Iterator<Item = &Number> {
+        self.numbers
+            .iter()
+            .filter(|number| number.symbols.len() > 1)
+    }
+}
+
+pub fn part1(input: &Input) -> usize {
+    input.schematic.parts().map(|number| number.value).sum()
+}
+
+pub fn part2(input: &Input) -> usize {
+    let schematic = &input.schematic;
+    schematic
+        .gears(b'*', 2)
+        .map(|gear| {
+            schematic
+                .parts_of(gear)
+                .map(|number| number.value)
+                .product::<usize>()
+        })
         .sum()
 }
@@ -1,3 +1,4 @@
+use crate::Error;
 use std::collections::HashSet;
 
 #[derive(Debug)]
@@ -12,6 +13,101 @@ impl From<&str> for Input {
     }
 }
 
+impl Input {
+    pub fn cascade(&self, rule: CopyRule) -> Result<Cascade, Error> {
+        let total_cards = self.cards.len();
+        let mut generations = vec![vec![1; total_cards]];
+        loop {
+            let layer = generations.last().unwrap();
+            if layer.iter().all(|&n| n == 0) {
+                generations.pop();
+                return Ok(Cascade { generations });
+            }
+            // A path longer than the amount of cards must revisit a card, so the
+            // cascade keeps producing copies forever.
+            if generations.len() > total_cards {
+                return Err(Error::Logic(format!(
+                    "cascade under {:?} never terminates",
+                    rule
+                )));
+            }
+
+            let mut next = vec![0usize; total_cards];
+            for (i, card) in self.cards.iter().enumerate() {
+                if layer[i] == 0 {
+                    continue;
+                }
+                let (targets, weight) = rule.apply(i, card, total_cards);
+                let amount = layer[i]
+                    .checked_mul(weight)
+                    .ok_or_else(|| Error::Logic("copy count overflow".to_string()))?;
+                for j in targets {
+                    next[j] = next[j]
+                        .checked_add(amount)
+                        .ok_or_else(|| Error::Logic("copy count overflow".to_string()))?;
+                }
+            }
+            generations.push(next);
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CopyRule {
+    Standard,
+    Wrap,
+    Worth,
+}
+
+impl CopyRule {
+    fn apply(&self, i: usize, card: &ScratchCard, total_cards: usize) -> (Vec<usize>, usize) {
+        let winnings = card.winnings();
+        let start = (i + 1).min(total_cards);
+        let end = (start + winnings).min(total_cards);
+        match self {
+            CopyRule::Standard => ((start..end).collect(), 1),
+            CopyRule::Wrap => ((1..=winnings).map(|n| (i + n) % total_cards).collect(), 1),
+            CopyRule::Worth => ((start..end).collect(), card.worth()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Cascade {
+    generations: Vec<Vec<usize>>,
+}
+
+impl Cascade {
+    pub fn generations(&self) -> &[Vec<usize>] {
+        &self.generations
+    }
+
+    pub fn depth(&self) -> usize {
+        self.generations.len()
+    }
+
+    pub fn copies(&self) -> Vec<usize> {
+        let cards = self.generations.first().map_or(0, Vec::len);
+        let mut copies = vec![0; cards];
+        for layer in &self.generations {
+            copies.iter_mut().zip(layer).for_each(|(n, m)| *n += m);
+        }
+        copies
+    }
+
+    pub fn arrivals(&self, card: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.generations
+            .iter()
+            .enumerate()
+            .map(move |(generation, layer)| (generation, layer[card]))
+            .filter(|&(_, n)| n > 0)
+    }
+
+    pub fn total(&self) -> usize {
+        self.generations.iter().flatten().sum()
+    }
+}
+
 #[derive(Clone, Debug)]
 struct ScratchCard {
     winning_numbers: HashSet<usize>,
@@ -55,22 +151,5 @@ pub fn part1(input: &Input) -> usize {
 }
 
 pub fn part2(input: &Input) -> usize {
-    let total_cards = input.cards.len();
-    input
-        .cards
-        .iter()
-        .enumerate()
-        .map(|(i, card)| {
-            let winnings = card.winnings();
-            let start = (i + 1).min(total_cards);
-            let end = (start + winnings).min(total_cards);
-            (i, start..end)
-        })
-        .fold(vec![1; total_cards], |mut pocket, (i, range)| {
-            let amount = pocket[i];
-            pocket[range].iter_mut().for_each(|n| *n += amount);
-            pocket
-        })
-        .iter()
-        .sum()
+    input.cascade(CopyRule::Standard).unwrap().total()
 }
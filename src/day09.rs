@@ -1,3 +1,7 @@
+use crate::Error;
+use std::fmt;
+use std::ops::{Add, Mul};
+
 #[derive(Debug)]
 pub struct Input {
     histories: Vec<History>,
@@ -10,8 +14,14 @@ impl From<&str> for Input {
     }
 }
 
+impl Input {
+    pub fn histories(&self) -> &[History] {
+        &self.histories
+    }
+}
+
 #[derive(Debug)]
-struct History {
+pub struct History {
     num: Vec<isize>,
 }
 
@@ -26,32 +36,193 @@ impl From<&str> for History {
 }
 
 impl History {
-    fn difference(&self) -> Self {
-        let num = self.num.windows(2).map(|w| w[1] - w[0]).collect();
-        Self { num }
+    pub fn polynomial(&self) -> Result<Polynomial, Error> {
+        let mut row: Vec<i128> = self.num.iter().map(|&n| n as i128).collect();
+        let mut newton = Vec::new();
+        while !row.is_empty() {
+            if row.iter().all(|&n| n == 0) {
+                return Ok(Polynomial {
+                    newton,
+                    len: self.num.len(),
+                });
+            }
+            newton.push(row[0]);
+            row = row.windows(2).map(|w| w[1] - w[0]).collect();
+        }
+        Err(Error::Logic(format!(
+            "history of {} values is not polynomial within its length",
+            self.num.len()
+        )))
+    }
+
+    pub fn forward(&self) -> Result<isize, Error> {
+        to_isize(self.polynomial()?.forward(1))
+    }
+
+    pub fn backward(&self) -> Result<isize, Error> {
+        to_isize(self.polynomial()?.backward(1))
     }
+}
 
-    fn forward(&self) -> isize {
-        if self.num.iter().all(|&n| n == 0) {
-            return 0;
+fn to_isize(value: i128) -> Result<isize, Error> {
+    isize::try_from(value)
+        .map_err(|_| Error::Logic(format!("extrapolated value {} is out of range", value)))
+}
+
+// Newton forward-difference form, p(x) = sum(newton[k] * C(x, k)), where x = 0
+// is the first value of the history.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Polynomial {
+    newton: Vec<i128>,
+    len: usize,
+}
+
+impl Polynomial {
+    pub fn degree(&self) -> usize {
+        self.newton.len().saturating_sub(1)
+    }
+
+    pub fn eval(&self, x: i128) -> i128 {
+        let mut binomial = 1;
+        let mut value = 0;
+        for (k, &delta) in self.newton.iter().enumerate() {
+            value += delta * binomial;
+            binomial = binomial * (x - k as i128) / (k as i128 + 1);
         }
+        value
+    }
 
-        self.num.last().unwrap() + self.difference().forward()
+    pub fn forward(&self, steps: usize) -> i128 {
+        self.eval((self.len - 1 + steps) as i128)
     }
 
-    fn backward(&self) -> isize {
-        if self.num.iter().all(|&n| n == 0) {
-            return 0;
+    pub fn backward(&self, steps: usize) -> i128 {
+        self.eval(-(steps as i128))
+    }
+
+    pub fn coefficients(&self) -> Vec<Rational> {
+        let mut coefficients = vec![Rational::from(0); self.newton.len()];
+        // Falling factorial x(x-1)...(x-k+1) in monomial form, and k!.
+        let mut falling = vec![1];
+        let mut factorial = 1;
+        for (k, &delta) in self.newton.iter().enumerate() {
+            for (j, &c) in falling.iter().enumerate() {
+                coefficients[j] = coefficients[j] + Rational::new(delta * c, factorial);
+            }
+
+            let mut next = vec![0; falling.len() + 1];
+            for (j, &c) in falling.iter().enumerate() {
+                next[j + 1] += c;
+                next[j] -= c * k as i128;
+            }
+            falling = next;
+            factorial *= k as i128 + 1;
         }
+        coefficients
+    }
+}
 
-        self.num.first().unwrap() - self.difference().backward()
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let terms: Vec<_> = self
+            .coefficients()
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, c)| c.num != 0)
+            .map(|(j, c)| match j {
+                0 => format!("{}", c),
+                1 => format!("({})x", c),
+                _ => format!("({})x^{}", c, j),
+            })
+            .collect();
+        if terms.is_empty() {
+            write!(f, "0")
+        } else {
+            write!(f, "{}", terms.join(" + "))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Rational {
+    pub fn new(num: i128, den: i128) -> Self {
+        assert!(den != 0, "zero denominator");
+        let sign = den.signum();
+        let divisor = gcd(num, den).max(1);
+        Self {
+            num: sign * num / divisor,
+            den: sign * den / divisor,
+        }
+    }
+
+    pub fn numer(&self) -> i128 {
+        self.num
+    }
+
+    pub fn denom(&self) -> i128 {
+        self.den
+    }
+}
+
+impl From<i128> for Rational {
+    fn from(n: i128) -> Self {
+        Self { num: n, den: 1 }
+    }
+}
+
+impl Add for Rational {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.num * rhs.den + rhs.num * self.den, self.den * rhs.den)
+    }
+}
+
+impl Mul for Rational {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(self.num * rhs.num, self.den * rhs.den)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
     }
 }
 
 pub fn part1(input: &Input) -> isize {
-    input.histories.iter().map(History::forward).sum()
+    input
+        .histories
+        .iter()
+        .map(History::forward)
+        .sum::<Result<_, _>>()
+        .unwrap()
 }
 
 pub fn part2(input: &Input) -> isize {
-    input.histories.iter().map(History::backward).sum()
+    input
+        .histories
+        .iter()
+        .map(History::backward)
+        .sum::<Result<_, _>>()
+        .unwrap()
 }
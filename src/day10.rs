@@ -1,7 +1,9 @@
-#![allow(clippy::reversed_empty_ranges)] 
+#![allow(clippy::reversed_empty_ranges)]
 
-use std::collections::HashSet;
+use crate::Error;
 use ndarray::{s, Array2};
+use std::collections::HashSet;
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Input {
@@ -10,10 +12,14 @@ pub struct Input {
 
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
-        let width: usize = s.lines().next().unwrap().len();
+        let width = s
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
         let inner_map: Vec<char> = s
             .lines()
-            .flat_map(|line| line.chars())
+            .flat_map(|line| line.chars().chain(std::iter::repeat('.')).take(width))
             .map(|c| match c {
                 'F' => '┌',
                 'J' => '┘',
@@ -22,7 +28,7 @@ impl From<&str> for Input {
                 c => c,
             })
             .collect();
-        let height = inner_map.len() / width;
+        let height = s.lines().count();
         let inner_map = Array2::from_shape_vec((height, width), inner_map).unwrap();
         let mut map = Array2::from_elem((height + 2, width + 2), '.');
         map.slice_mut(s![1..-1, 1..-1]).assign(&inner_map);
@@ -30,8 +36,8 @@ impl From<&str> for Input {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-enum Direction {
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -48,100 +54,354 @@ impl Direction {
         ]
         .into_iter()
     }
+
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    fn step(self, (r, c): Coord) -> Coord {
+        match self {
+            Direction::Up => (r - 1, c),
+            Direction::Down => (r + 1, c),
+            Direction::Left => (r, c - 1),
+            Direction::Right => (r, c + 1),
+        }
+    }
 }
 
 type Map = Array2<char>;
 type Coord = (usize, usize);
 
-fn traverse(map: &Map, mut curr: Coord, mut direction: Direction) -> Option<(Vec<Coord>, char)> {
-    let first_direction = direction;
-    let mut path = Vec::new();
-    'outer: loop {
-        path.push(curr);
-
-        let tile = map[curr];
-        curr = match (tile, direction) {
-            ('S' | '┘' | '└' | '|', Direction::Up) => (curr.0 - 1, curr.1),
-            ('S' | '┌' | '┐' | '|', Direction::Down) => (curr.0 + 1, curr.1),
-            ('S' | '┘' | '┐' | '-', Direction::Left) => (curr.0, curr.1 - 1),
-            ('S' | '└' | '┌' | '-', Direction::Right) => (curr.0, curr.1 + 1),
-            _ => return None,
+fn connections(tile: char) -> &'static [Direction] {
+    match tile {
+        '|' => &[Direction::Up, Direction::Down],
+        '-' => &[Direction::Left, Direction::Right],
+        '┘' => &[Direction::Up, Direction::Left],
+        '└' => &[Direction::Up, Direction::Right],
+        '┐' => &[Direction::Down, Direction::Left],
+        '┌' => &[Direction::Down, Direction::Right],
+        _ => &[],
+    }
+}
+
+fn piece(a: Direction, b: Direction) -> char {
+    ['|', '-', '┘', '└', '┐', '┌']
+        .into_iter()
+        .find(|&tile| {
+            let dirs = connections(tile);
+            dirs.contains(&a) && dirs.contains(&b)
+        })
+        .unwrap()
+}
+
+// Whether the pipe leaving `start` towards `dir` leads back into it.
+fn closes(map: &Map, start: Coord, dir: Direction) -> bool {
+    let mut curr = start;
+    let mut direction = dir;
+    loop {
+        let next = direction.step(curr);
+        if !connections(map[next]).contains(&direction.opposite()) {
+            return false;
+        }
+        if next == start {
+            return true;
+        }
+        curr = next;
+        direction = *connections(map[curr])
+            .iter()
+            .find(|&&dir| dir != direction.opposite())
+            .unwrap();
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Defect {
+    Dangling { at: Coord, towards: Direction },
+    OffMap { at: Coord, towards: Direction },
+}
+
+// Every pipe has at most two ends, so a set of mutually connected pipes is
+// either a closed loop or an open chain, and the two are walked the same way.
+#[derive(Debug)]
+pub struct Network {
+    map: Rc<Map>,
+    start: Coord,
+    loops: Vec<Vec<Coord>>,
+    defects: Vec<Defect>,
+}
+
+impl Network {
+    fn new(map: &Map) -> Result<Self, Error> {
+        let starts: Vec<_> = map
+            .indexed_iter()
+            .filter_map(|(coord, c)| if *c == 'S' { Some(coord) } else { None })
+            .collect();
+        let start = match starts[..] {
+            [start] => start,
+            [] => return Err(Error::Logic("map has no start tile".to_string())),
+            _ => {
+                return Err(Error::Logic(format!(
+                    "map has {} start tiles",
+                    starts.len()
+                )))
+            }
+        };
+
+        let candidates: Vec<_> = Direction::iter()
+            .filter(|dir| connections(map[dir.step(start)]).contains(&dir.opposite()))
+            .collect();
+        // Pipes pointing at the start need not be part of its loop, so every
+        // pair of them is tried and the start must close exactly one loop. An
+        // open start with just two neighbours is kept for its defects.
+        let mut map = map.clone();
+        let mut closing = Vec::new();
+        for (i, &a) in candidates.iter().enumerate() {
+            for &b in &candidates[i + 1..] {
+                map[start] = piece(a, b);
+                if closes(&map, start, a) {
+                    closing.push(map[start]);
+                }
+            }
+        }
+        map[start] = match (&closing[..], &candidates[..]) {
+            ([tile], _) => *tile,
+            ([], &[a, b]) => piece(a, b),
+            _ => {
+                return Err(Error::Logic(format!(
+                    "start tile at {:?} closes {} loops among {} connecting neighbours, expected 1",
+                    (start.0 - 1, start.1 - 1),
+                    closing.len(),
+                    candidates.len()
+                )))
+            }
+        };
+
+        let (height, width) = map.dim();
+        let linked = |coord: Coord, dir: Direction| {
+            let next = dir.step(coord);
+            connections(map[next]).contains(&dir.opposite())
         };
 
-        let tile = map[curr];
-        direction = match (tile, direction) {
-            ('.', _) => return None,
-            ('S', _) => break 'outer,
-            ('|', _) => direction,
-            ('-', _) => direction,
-            ('┘', Direction::Right) => Direction::Up,
-            ('┘', Direction::Down) => Direction::Left,
-            ('└', Direction::Down) => Direction::Right,
-            ('└', Direction::Left) => Direction::Up,
-            ('┌', Direction::Left) => Direction::Down,
-            ('┌', Direction::Up) => Direction::Right,
-            ('┐', Direction::Up) => Direction::Left,
-            ('┐', Direction::Right) => Direction::Down,
-            combo => unreachable!("invalid combination: {:?}", combo),
+        let mut defects = Vec::new();
+        for (coord, &tile) in map.indexed_iter() {
+            for &dir in connections(tile) {
+                if linked(coord, dir) {
+                    continue;
+                }
+                let (r, c) = dir.step(coord);
+                let at = (coord.0 - 1, coord.1 - 1);
+                if r == 0 || c == 0 || r == height - 1 || c == width - 1 {
+                    defects.push(Defect::OffMap { at, towards: dir });
+                } else {
+                    defects.push(Defect::Dangling { at, towards: dir });
+                }
+            }
         }
+
+        let mut loops = Vec::new();
+        let mut visited = HashSet::new();
+        for (coord, &tile) in map.indexed_iter() {
+            let dirs = connections(tile);
+            if dirs.is_empty() || visited.contains(&coord) {
+                continue;
+            }
+
+            let mut path = vec![coord];
+            let mut closed = false;
+            let mut curr = coord;
+            let mut direction = dirs[0];
+            visited.insert(coord);
+            while linked(curr, direction) {
+                curr = direction.step(curr);
+                if curr == coord {
+                    closed = true;
+                    break;
+                }
+                visited.insert(curr);
+                path.push(curr);
+                direction = *connections(map[curr])
+                    .iter()
+                    .find(|&&dir| dir != direction.opposite())
+                    .unwrap();
+            }
+
+            // Open chains are walked from both ends so every pipe is marked.
+            if !closed {
+                let mut curr = coord;
+                let mut direction = dirs[1];
+                while linked(curr, direction) {
+                    curr = direction.step(curr);
+                    visited.insert(curr);
+                    direction = *connections(map[curr])
+                        .iter()
+                        .find(|&&dir| dir != direction.opposite())
+                        .unwrap();
+                }
+            } else {
+                loops.push(path);
+            }
+        }
+
+        Ok(Self {
+            map: Rc::new(map),
+            start,
+            loops,
+            defects,
+        })
     }
 
-    let piece = match (direction, first_direction) {
-        (Direction::Up, Direction::Up) | (Direction::Down, Direction::Down) => '|',
-        (Direction::Left, Direction::Left) | (Direction::Right, Direction::Right) => '-',
-        (Direction::Right, Direction::Up) | (Direction::Down, Direction::Left) => '┘',
-        (Direction::Up, Direction::Right) | (Direction::Left, Direction::Down) => '┌',
-        (Direction::Down, Direction::Right) | (Direction::Left, Direction::Up) => '└',
-        (Direction::Up, Direction::Left) | (Direction::Right, Direction::Down) => '┐',
-        combo => unreachable!("invalid combination: {:?}", combo),
-    };
+    pub fn loops(&self) -> impl Iterator<Item = PipeLoop> + '_ {
+        self.loops.iter().map(|path| PipeLoop {
+            map: self.map.clone(),
+            path: path.clone(),
+        })
+    }
+
+    pub fn defects(&self) -> &[Defect] {
+        &self.defects
+    }
+
+    pub fn start_tile(&self) -> char {
+        self.map[self.start]
+    }
 
-    Some((path, piece))
+    pub fn main_loop(&self) -> Result<PipeLoop, Error> {
+        let path = self
+            .loops
+            .iter()
+            .find(|path| path.contains(&self.start))
+            .ok_or_else(|| {
+                let start = (self.start.0 - 1, self.start.1 - 1);
+                let defects: Vec<_> = self.defects.iter().map(|d| format!("{:?}", d)).collect();
+                Error::Logic(format!(
+                    "start tile at {:?} is not on a closed loop, defects: [{}]",
+                    start,
+                    defects.join(", ")
+                ))
+            })?;
+        Ok(PipeLoop {
+            map: self.map.clone(),
+            path: path.clone(),
+        })
+    }
 }
 
-fn explore(map: &Map, start: Coord) -> (Vec<Coord>, char) {
-    Direction::iter()
-        .filter_map(|dir| traverse(map, start, dir))
-        .next()
-        .unwrap()
+impl Input {
+    pub fn analyze(&self) -> Result<Network, Error> {
+        Network::new(&self.map)
+    }
+
+    pub fn main_loop(&self) -> Result<PipeLoop, Error> {
+        self.analyze()?.main_loop()
+    }
 }
 
-pub fn part1(input: &Input) -> usize {
-    let start = input
-        .map
-        .indexed_iter()
-        .find_map(|(coord, c)| if *c == 'S' { Some(coord) } else { None })
-        .unwrap();
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    Loop,
+    Inside,
+    Outside,
+}
 
-    let (path, _) = explore(&input.map, start);
-    path.len() / 2
+// The loop keeps a copy of the padded map with `S` replaced by its real pipe,
+// coordinates handed out are relative to the unpadded input.
+#[derive(Clone, Debug)]
+pub struct PipeLoop {
+    map: Rc<Map>,
+    path: Vec<Coord>,
 }
 
-pub fn part2(input: &Input) -> usize {
-    let start = input
-        .map
-        .indexed_iter()
-        .find_map(|(coord, c)| if *c == 'S' { Some(coord) } else { None })
-        .unwrap();
-
-    let (path, start_tile) = explore(&input.map, start);
-    let path: HashSet<Coord> = path.into_iter().collect();
-
-    let mut map = input.map.clone();
-    map[start] = start_tile;
-
-    let mut total_inside = 0;
-    for (r, row) in map.rows().into_iter().enumerate() {
-        let mut inside = false;
-        for (c, tile) in row.iter().enumerate() {
-            let coord = (r, c);
-            match *tile {
-                '|' | '┌' | '┐' if path.contains(&coord) => inside = !inside,
-                _ if inside && !path.contains(&coord) => total_inside += 1,
-                _ => {}
+impl PipeLoop {
+    pub fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    pub fn vertices(&self) -> Vec<Coord> {
+        self.path
+            .iter()
+            .filter(|&&coord| matches!(self.map[coord], '┌' | '┐' | '└' | '┘'))
+            .map(|&(r, c)| (r - 1, c - 1))
+            .collect()
+    }
+
+    fn twice_area(&self) -> usize {
+        let vertices = self.vertices();
+        let shoelace: isize = vertices
+            .iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .map(|(&(r0, c0), &(r1, c1))| (c0 * r1) as isize - (c1 * r0) as isize)
+            .sum();
+        shoelace.unsigned_abs()
+    }
+
+    pub fn area(&self) -> f64 {
+        self.twice_area() as f64 / 2.0
+    }
+
+    pub fn interior(&self) -> usize {
+        // Pick's theorem, A = I + B/2 - 1, with every loop tile being a boundary point.
+        (self.twice_area() + 2 - self.path.len()) / 2
+    }
+
+    pub fn classify(&self) -> Array2<Tile> {
+        let path: HashSet<Coord> = self.path.iter().copied().collect();
+        let inner = self.map.slice(s![1..-1, 1..-1]);
+        let mut tiles = Array2::from_elem(inner.dim(), Tile::Outside);
+        for (r, row) in inner.rows().into_iter().enumerate() {
+            let mut inside = false;
+            for (c, tile) in row.iter().enumerate() {
+                let on_path = path.contains(&(r + 1, c + 1));
+                match *tile {
+                    '|' | '┌' | '┐' if on_path => inside = !inside,
+                    _ => {}
+                }
+                if on_path {
+                    tiles[(r, c)] = Tile::Loop;
+                } else if inside {
+                    tiles[(r, c)] = Tile::Inside;
+                }
             }
         }
+        tiles
     }
 
+    pub fn render(&self) -> String {
+        let inner = self.map.slice(s![1..-1, 1..-1]);
+        let mut out = String::new();
+        for (tiles, glyphs) in self.classify().rows().into_iter().zip(inner.rows()) {
+            for (tile, glyph) in tiles.iter().zip(glyphs) {
+                out.push(match tile {
+                    Tile::Loop => *glyph,
+                    Tile::Inside => 'I',
+                    Tile::Outside => 'O',
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+pub fn part1(input: &Input) -> usize {
+    input.main_loop().unwrap().len() / 2
+}
+
+pub fn part2(input: &Input) -> usize {
+    let pipe_loop = input.main_loop().unwrap();
+    let total_inside = pipe_loop
+        .classify()
+        .iter()
+        .filter(|&&tile| tile == Tile::Inside)
+        .count();
+    debug_assert_eq!(total_inside, pipe_loop.interior());
     total_inside
 }
@@ -1,73 +1,204 @@
-use ndarray::{Array2, Axis};
-
 #[derive(Debug)]
 pub struct Input {
-    map: Map,
+    universe: Universe,
 }
 
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
-        let width: usize = s.lines().next().unwrap().len();
-        let map: Vec<char> = s.lines().flat_map(|line| line.chars()).collect();
-        let height: usize = map.len() / width;
-        let map = Array2::from_shape_vec((height, width), map).unwrap();
-        Self { map }
+        let galaxies = s
+            .lines()
+            .enumerate()
+            .flat_map(|(r, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter_map(move |(c, v)| if v == '#' { Some((r, c)) } else { None })
+            })
+            .collect();
+        let universe = Universe::new(galaxies);
+        Self { universe }
+    }
+}
+
+impl Input {
+    pub fn universe(&self) -> &Universe {
+        &self.universe
     }
 }
 
 type Coord = (usize, usize);
-type Map = Array2<char>;
 
 fn manhatten_distance(x: Coord, y: Coord) -> usize {
     x.0.abs_diff(y.0) + x.1.abs_diff(y.1)
 }
 
-fn empty_lanes(map: &Map, axis: Axis) -> Vec<usize> {
-    map.axis_iter(axis)
-        .enumerate()
-        .filter_map(|(r, row)| {
-            if row.into_iter().all(|v| *v == '.') {
-                Some(r)
-            } else {
-                None
-            }
+// Expands one axis, where every lane without a galaxy below a coordinate
+// grows to `factor` lanes. The amount of empty lanes below a value is the
+// value itself minus the amount of distinct occupied lanes below it.
+fn expand_axis(values: impl Iterator<Item = usize>, factor: usize) -> Vec<usize> {
+    let values: Vec<_> = values.collect();
+    let mut occupied = values.clone();
+    occupied.sort_unstable();
+    occupied.dedup();
+    values
+        .into_iter()
+        .map(|v| {
+            let rank = occupied.partition_point(|&o| o < v);
+            v + (v - rank) * (factor - 1)
         })
         .collect()
 }
 
-fn expand_universe(map: &Map, n: usize) -> usize {
-    let empty_rows = empty_lanes(map, Axis(0));
-    let empty_cols = empty_lanes(map, Axis(1));
-
-    let galaxies: Vec<_> = map
-        .indexed_iter()
-        .filter_map(|(coord, c)| if *c == '#' { Some(coord) } else { None })
-        .map(|coord| {
-            let expand_row = empty_rows.iter().take_while(|r| **r < coord.0).count();
-            let expand_col = empty_cols.iter().take_while(|c| **c < coord.1).count();
-            (
-                coord.0 + expand_row * (n - 1),
-                coord.1 + expand_col * (n - 1),
-            )
-        })
+fn axis_distance_sum(mut values: Vec<usize>) -> u128 {
+    values.sort_unstable();
+    let mut prefix = 0;
+    let mut summa = 0;
+    for (i, v) in values.into_iter().enumerate() {
+        let v = v as u128;
+        summa += v * i as u128 - prefix;
+        prefix += v;
+    }
+    summa
+}
+
+#[derive(Debug)]
+pub struct Universe {
+    galaxies: Vec<Coord>,
+}
+
+impl Universe {
+    pub fn new(galaxies: Vec<Coord>) -> Self {
+        Self { galaxies }
+    }
+
+    pub fn galaxies(&self) -> &[Coord] {
+        &self.galaxies
+    }
+
+    pub fn expand(&self, row_factor: usize, col_factor: usize) -> Expanded {
+        assert!(
+            row_factor > 0 && col_factor > 0,
+            "expansion factor must be positive"
+        );
+        let rows = expand_axis(self.galaxies.iter().map(|g| g.0), row_factor);
+        let cols = expand_axis(self.galaxies.iter().map(|g| g.1), col_factor);
+        let galaxies: Vec<Coord> = rows.into_iter().zip(cols).collect();
+
+        let mut order: Vec<_> = (0..galaxies.len()).collect();
+        order.sort_unstable_by_key(|&i| galaxies[i]);
+        let mut position = vec![0; galaxies.len()];
+        let mut rows = Vec::new();
+        for (p, &i) in order.iter().enumerate() {
+            position[i] = p;
+            if p == 0 || galaxies[order[p - 1]].0 != galaxies[i].0 {
+                rows.push(p);
+            }
+        }
+        rows.push(order.len());
+
+        let sum = |j: &usize| galaxies[*j].0 + galaxies[*j].1;
+        let diff = |j: &usize| galaxies[*j].0 as isize - galaxies[*j].1 as isize;
+        let all = 0..galaxies.len();
+        let extremes = [
+            all.clone().min_by_key(sum),
+            all.clone().max_by_key(sum),
+            all.clone().min_by_key(diff),
+            all.max_by_key(diff),
+        ]
+        .into_iter()
+        .flatten()
         .collect();
 
-    galaxies
-        .iter()
-        .enumerate()
-        .flat_map(|(i, coord0)| {
-            galaxies[i + 1..]
-                .iter()
-                .map(move |coord1| (*coord0, *coord1))
-        })
-        .map(|(coord0, coord1)| manhatten_distance(coord0, coord1))
-        .sum()
+        Expanded {
+            galaxies,
+            order,
+            position,
+            rows,
+            extremes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Expanded {
+    galaxies: Vec<Coord>,
+    order: Vec<usize>,
+    position: Vec<usize>,
+    // Where each row starts in `order`, followed by its length.
+    rows: Vec<usize>,
+    extremes: Vec<usize>,
+}
+
+impl Expanded {
+    pub fn galaxies(&self) -> &[Coord] {
+        &self.galaxies
+    }
+
+    pub fn distance_sum(&self) -> u128 {
+        axis_distance_sum(self.galaxies.iter().map(|g| g.0).collect())
+            + axis_distance_sum(self.galaxies.iter().map(|g| g.1).collect())
+    }
+
+    // Returns the index of the closest other galaxy and its distance. Scans
+    // rows outwards from the galaxy's own, stopping once the row gap alone
+    // exceeds the best, and within a row outwards from its column, stopping
+    // once the distance does.
+    pub fn nearest(&self, i: usize) -> Option<(usize, usize)> {
+        let origin = self.galaxies[i];
+        let own = self
+            .rows
+            .partition_point(|&start| start <= self.position[i])
+            - 1;
+        let mut best: Option<(usize, usize)> = None;
+        let mut scan = |r: usize| {
+            let row = &self.order[self.rows[r]..self.rows[r + 1]];
+            let gap = origin.0.abs_diff(self.galaxies[row[0]].0);
+            if best.is_some_and(|(_, d)| gap > d) {
+                return false;
+            }
+            let mid = row.partition_point(|&j| self.galaxies[j].1 < origin.1);
+            for side in [
+                &mut row[mid..].iter() as &mut dyn Iterator<Item = &usize>,
+                &mut row[..mid].iter().rev(),
+            ] {
+                for &j in side.filter(|&&j| j != i) {
+                    let d = gap + origin.1.abs_diff(self.galaxies[j].1);
+                    if best.is_some_and(|(_, bd)| d >= bd) {
+                        break;
+                    }
+                    best = Some((j, d));
+                }
+            }
+            true
+        };
+        for r in (0..=own).rev() {
+            if !scan(r) {
+                break;
+            }
+        }
+        for r in own + 1..self.rows.len() - 1 {
+            if !scan(r) {
+                break;
+            }
+        }
+        best
+    }
+
+    // The farthest galaxy in Manhattan distance is an extreme of either
+    // r + c or r - c, so only those four candidates need checking.
+    pub fn farthest(&self, i: usize) -> Option<(usize, usize)> {
+        let origin = self.galaxies[i];
+        self.extremes
+            .iter()
+            .filter(|&&j| j != i)
+            .map(|&j| (j, manhatten_distance(origin, self.galaxies[j])))
+            .max_by_key(|&(_, d)| d)
+    }
 }
 
 pub fn part1(input: &Input) -> usize {
-    expand_universe(&input.map, 2)
+    input.universe.expand(2, 2).distance_sum() as usize
 }
 
 pub fn part2(input: &Input) -> usize {
-    expand_universe(&input.map, 1_000_000)
+    input.universe.expand(1_000_000, 1_000_000).distance_sum() as usize
 }
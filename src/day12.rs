@@ -1,3 +1,6 @@
+use crate::Error;
+use rand::Rng;
+
 #[derive(Debug)]
 pub struct Input {
     springs: Vec<Spring>,
@@ -10,8 +13,14 @@ impl From<&str> for Input {
     }
 }
 
-#[derive(Debug)]
-struct Spring {
+impl Input {
+    pub fn springs(&self) -> &[Spring] {
+        &self.springs
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Spring {
     condition: Vec<char>,
     groups: Vec<usize>,
 }
@@ -25,69 +34,295 @@ impl From<&str> for Spring {
     }
 }
 
-#[memoize::memoize]
-fn matches(springs: Vec<char>, groups: Vec<usize>) -> Option<usize> {
-    if let Some((&n, groups_left)) = groups.split_first() {
-        let token = if groups_left.is_empty() {
-            "#".repeat(n)
-        } else {
-            format!("{}.", "#".repeat(n))
-        };
+fn overflow() -> Error {
+    Error::Logic("arrangement count overflow".to_string())
+}
 
-        if token.len() > springs.len() {
-            return None;
+// Amount of ways `condition[i..]` can hold `groups[g..]`, indexed as
+// `counts[i * (groups + 1) + g]`. Filled back to front so every state is
+// computed exactly once, without touching the heap per state.
+#[derive(Debug)]
+struct Table {
+    counts: Vec<u128>,
+    run: Vec<usize>,
+    width: usize,
+}
+
+impl Table {
+    fn new(condition: &[char], groups: &[usize]) -> Result<Self, Error> {
+        let n = condition.len();
+        let m = groups.len();
+        let width = m + 1;
+
+        // Length of the run of possibly damaged springs starting at each position.
+        let mut run = vec![0; n + 1];
+        for i in (0..n).rev() {
+            if condition[i] != '.' {
+                run[i] = run[i + 1] + 1;
+            }
         }
 
-        let mut summa = 0;
-        for i in 0..=(springs.len() - token.len()) {
-            if springs[..i].iter().any(|&c| c == '#') {
-                break;
+        let mut table = Self {
+            counts: vec![0; (n + 1) * width],
+            run,
+            width,
+        };
+        table.counts[n * width + m] = 1;
+        for i in (0..n).rev() {
+            for g in 0..=m {
+                let ways = table
+                    .dot(condition, i, g)
+                    .checked_add(table.group(condition, groups, i, g))
+                    .ok_or_else(overflow)?;
+                table.counts[i * width + g] = ways;
             }
-            let (head, tail) = springs[i..].split_at(token.len());
-
-            if !head
-                .iter()
-                .zip(token.chars())
-                .all(|(&l, r)| l == '?' || l == r)
-            {
-                continue;
+        }
+
+        Ok(table)
+    }
+
+    fn get(&self, i: usize, g: usize) -> u128 {
+        self.counts[i * self.width + g]
+    }
+
+    // Position following group `g` when placed at `i`, including its separator.
+    fn place(&self, condition: &[char], groups: &[usize], i: usize, g: usize) -> Option<usize> {
+        let n = condition.len();
+        let k = *groups.get(g)?;
+        if self.run[i] >= k && (i + k == n || condition[i + k] != '#') {
+            Some((i + k + 1).min(n))
+        } else {
+            None
+        }
+    }
+
+    fn dot(&self, condition: &[char], i: usize, g: usize) -> u128 {
+        if condition[i] != '#' {
+            self.get(i + 1, g)
+        } else {
+            0
+        }
+    }
+
+    fn group(&self, condition: &[char], groups: &[usize], i: usize, g: usize) -> u128 {
+        self.place(condition, groups, i, g)
+            .map_or(0, |next| self.get(next, g + 1))
+    }
+}
+
+impl Spring {
+    pub fn new(condition: &str, groups: &[usize]) -> Self {
+        Self {
+            condition: condition.chars().collect(),
+            groups: groups.to_vec(),
+        }
+    }
+
+    pub fn condition(&self) -> &[char] {
+        &self.condition
+    }
+
+    pub fn groups(&self) -> &[usize] {
+        &self.groups
+    }
+
+    pub fn unfold(&self, factor: usize) -> Spring {
+        let mut condition = self.condition.clone();
+        condition.push('?');
+        condition = condition.repeat(factor);
+        condition.pop();
+        let groups = self.groups.repeat(factor);
+        Spring { condition, groups }
+    }
+
+    pub fn arrangements(&self) -> Result<u128, Error> {
+        Ok(Table::new(&self.condition, &self.groups)?.get(0, 0))
+    }
+
+    pub fn iter(&self) -> Result<ArrangementIter<'_>, Error> {
+        ArrangementIter::new(self)
+    }
+
+    // Walks the table from the start, picking each branch proportionally to
+    // the amount of arrangements behind it.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Result<Option<String>, Error> {
+        let (condition, groups) = (&self.condition[..], &self.groups[..]);
+        let table = Table::new(condition, groups)?;
+        if table.get(0, 0) == 0 {
+            return Ok(None);
+        }
+
+        let mut arrangement = String::new();
+        let (mut i, mut g) = (0, 0);
+        while i < condition.len() {
+            let dot = table.dot(condition, i, g);
+            let group = table.group(condition, groups, i, g);
+            if rng.gen_range(0..dot + group) < dot {
+                arrangement.push('.');
+                i += 1;
+            } else {
+                let k = groups[g];
+                arrangement.extend(std::iter::repeat_n('#', k));
+                if i + k < condition.len() {
+                    arrangement.push('.');
+                }
+                i = (i + k + 1).min(condition.len());
+                g += 1;
             }
+        }
+        Ok(Some(arrangement))
+    }
+
+    // Counts, per cell, how many arrangements have it damaged by combining the
+    // amount of ways to reach a group placement with the ways to finish after it.
+    // Placements are tallied where they start and where they end, and a cell is
+    // damaged by those started but not yet ended.
+    pub fn damaged_counts(&self) -> Result<(u128, Vec<u128>), Error> {
+        let (condition, groups) = (&self.condition[..], &self.groups[..]);
+        let n = condition.len();
+        let m = groups.len();
+        let table = Table::new(condition, groups)?;
 
-            match matches(tail.to_owned(), groups_left.to_owned()) {
-                Some(m) => summa += m,
-                None => continue,
+        let add = |a: u128, b: u128| a.checked_add(b).ok_or_else(overflow);
+        let mut reach = vec![vec![0u128; m + 1]; n + 1];
+        reach[0][0] = 1;
+        let mut starts = vec![0u128; n + 1];
+        let mut ends = vec![0u128; n + 1];
+        for i in 0..n {
+            for g in 0..=m {
+                let ways = reach[i][g];
+                if ways == 0 {
+                    continue;
+                }
+                if condition[i] != '#' {
+                    reach[i + 1][g] = add(reach[i + 1][g], ways)?;
+                }
+                if let Some(next) = table.place(condition, groups, i, g) {
+                    reach[next][g + 1] = add(reach[next][g + 1], ways)?;
+                    let through = ways
+                        .checked_mul(table.get(next, g + 1))
+                        .ok_or_else(overflow)?;
+                    starts[i] = add(starts[i], through)?;
+                    ends[i + groups[g]] = add(ends[i + groups[g]], through)?;
+                }
             }
         }
-        Some(summa)
-    } else if springs.iter().any(|&c| c == '#') {
-        None
-    } else {
-        Some(1)
+
+        let mut damaged = Vec::with_capacity(n);
+        let (mut started, mut ended) = (0, 0);
+        for i in 0..n {
+            started = add(started, starts[i])?;
+            ended = add(ended, ends[i])?;
+            damaged.push(started - ended);
+        }
+        Ok((table.get(0, 0), damaged))
+    }
+
+    pub fn forced(&self) -> Result<Vec<(usize, char)>, Error> {
+        let (total, damaged) = self.damaged_counts()?;
+        if total == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(self
+            .condition
+            .iter()
+            .zip(damaged)
+            .enumerate()
+            .filter(|(_, (&c, _))| c == '?')
+            .filter_map(|(i, (_, d))| match d {
+                0 => Some((i, '.')),
+                d if d == total => Some((i, '#')),
+                _ => None,
+            })
+            .collect())
     }
 }
 
-impl Spring {
-    fn arrangements(&self) -> usize {
-        matches(self.condition.to_owned(), self.groups.to_owned()).unwrap_or(0)
+pub struct ArrangementIter<'s> {
+    spring: &'s Spring,
+    table: Table,
+    stack: Vec<(usize, usize, bool)>,
+    current: Vec<char>,
+    empty: bool,
+}
+
+impl<'s> ArrangementIter<'s> {
+    fn new(spring: &'s Spring) -> Result<Self, Error> {
+        let table = Table::new(&spring.condition, &spring.groups)?;
+        let empty = spring.condition.is_empty() && table.get(0, 0) > 0;
+        let mut iter = Self {
+            spring,
+            table,
+            stack: Vec::new(),
+            current: Vec::new(),
+            empty,
+        };
+        if !spring.condition.is_empty() {
+            iter.push_branches(0, 0);
+        }
+        Ok(iter)
+    }
+
+    // Only branches with arrangements behind them are pushed, so the walk
+    // never backtracks out of a dead end.
+    fn push_branches(&mut self, i: usize, g: usize) {
+        let (condition, groups) = (&self.spring.condition[..], &self.spring.groups[..]);
+        if self.table.group(condition, groups, i, g) > 0 {
+            self.stack.push((i, g, true));
+        }
+        if self.table.dot(condition, i, g) > 0 {
+            self.stack.push((i, g, false));
+        }
+    }
+}
+
+impl Iterator for ArrangementIter<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.empty {
+            self.empty = false;
+            return Some(String::new());
+        }
+
+        let n = self.spring.condition.len();
+        loop {
+            let (i, g, group) = self.stack.pop()?;
+            self.current.truncate(i);
+            let (next, g) = if group {
+                let k = self.spring.groups[g];
+                self.current.extend(std::iter::repeat_n('#', k));
+                if i + k < n {
+                    self.current.push('.');
+                }
+                ((i + k + 1).min(n), g + 1)
+            } else {
+                self.current.push('.');
+                (i + 1, g)
+            };
+
+            if next == n {
+                return Some(self.current.iter().collect());
+            }
+            self.push_branches(next, g);
+        }
     }
 }
 
 pub fn part1(input: &Input) -> usize {
-    input.springs.iter().map(Spring::arrangements).sum()
+    input
+        .springs
+        .iter()
+        .map(|spring| spring.arrangements().map(|n| n as usize))
+        .sum::<Result<_, _>>()
+        .unwrap()
 }
 
 pub fn part2(input: &Input) -> usize {
     input
         .springs
         .iter()
-        .map(|spring| {
-            let mut condition = spring.condition.clone();
-            condition.push('?');
-            condition = condition.repeat(5);
-            condition.pop();
-            let groups = spring.groups.repeat(5);
-            Spring { condition, groups }
-        })
-        .map(|spring| spring.arrangements())
-        .sum()
+        .map(|spring| spring.unfold(5).arrangements().map(|n| n as usize))
+        .sum::<Result<_, _>>()
+        .unwrap()
 }
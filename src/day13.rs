@@ -1,5 +1,4 @@
-use ndarray::{s, Array2, Axis, Slice};
-use std::ops::Sub;
+use ndarray::{Array2, Axis};
 
 #[derive(Debug)]
 pub struct Input {
@@ -15,7 +14,7 @@ impl From<&str> for Input {
 }
 
 #[derive(Clone, Debug)]
-struct Mirror(Array2<char>);
+pub struct Mirror(Array2<char>);
 
 impl From<&str> for Mirror {
     fn from(s: &str) -> Self {
@@ -27,67 +26,72 @@ impl From<&str> for Mirror {
     }
 }
 
-impl Mirror {
-    fn reflections(&self, axis: Axis) -> usize {
-        let axis_iter = self.0.axis_iter(axis);
-        let axis_iter2 = axis_iter.clone();
-
-        axis_iter
-            .zip(axis_iter2.skip(1))
-            .enumerate()
-            .filter_map(|(i, (l, r))| if l == r { Some(i as isize) } else { None })
-            .find(|&ind| {
-                let up = ind;
-
-                let down = self.0.shape()[axis.0] as isize - 1 - ind - 1;
-                let range = up.min(down);
-
-                let up_slice = Slice::new(ind - range, Some(ind + 1), -1);
-                let down_slice = Slice::new(ind + 1, Some(ind + 1 + range + 1), 1);
-
-                self.0
-                    .slice_axis(axis, up_slice)
-                    .axis_iter(axis)
-                    .zip(self.0.slice_axis(axis, down_slice).axis_iter(axis))
-                    .all(|(l, r)| l == r)
-            })
-            .map(|ind| (ind + 1) as usize)
-            .unwrap_or(0)
-    }
-
-    fn smudge(&self) -> usize {
-        let smudge: Array2<isize> = self.0.map(|&c| if c == '#' { 1 } else { 0 });
-        let shape = smudge.shape();
-        let rows = shape[0];
-        let cols = shape[1];
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
 
-        for r in 0..(rows - 1) {
-            let range = (r + 1).min(rows - r - 1);
-            let up_range = r.saturating_sub(range - 1)..(r + 1);
-            let down_range = (r + 1)..(r + 1 + range).min(rows);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reflection {
+    pub orientation: Orientation,
+    pub index: usize,
+    pub differences: Vec<(usize, usize)>,
+}
 
-            let up = smudge.slice(s![up_range;-1,..]);
-            let down = smudge.slice(s![down_range, ..]);
-            let diff = up.sub(&down);
-            if diff.iter().filter(|&&v| v != 0).count() == 1 {
-                return (r + 1) * 100;
-            }
+impl Reflection {
+    pub fn summary(&self) -> usize {
+        match self.orientation {
+            Orientation::Horizontal => self.index * 100,
+            Orientation::Vertical => self.index,
         }
+    }
+}
 
-        for c in 0..(cols - 1) {
-            let range = (c + 1).min(cols - c - 1);
-            let up_range = c.saturating_sub(range - 1)..(c + 1);
-            let down_range = (c + 1)..(c + 1 + range).min(cols);
-
-            let up = smudge.slice(s![..,up_range;-1]);
-            let down = smudge.slice(s![.., down_range]);
-            let diff = up.sub(&down);
-            if diff.iter().filter(|&&v| v != 0).count() == 1 {
-                return c + 1;
+impl Mirror {
+    // Returns every axis whose mirrored cells differ in exactly `k` places,
+    // where `index` is the amount of rows above or columns left of the axis.
+    pub fn reflections_with_tolerance(&self, k: usize) -> Vec<Reflection> {
+        let mut reflections = Vec::new();
+        for (orientation, axis) in [
+            (Orientation::Horizontal, Axis(0)),
+            (Orientation::Vertical, Axis(1)),
+        ] {
+            let len = self.0.len_of(axis);
+            for index in 1..len {
+                let range = index.min(len - index);
+                let mut differences = Vec::new();
+                for d in 0..range {
+                    let l = self.0.index_axis(axis, index - 1 - d);
+                    let r = self.0.index_axis(axis, index + d);
+                    for (j, (a, b)) in l.iter().zip(r.iter()).enumerate() {
+                        if a != b {
+                            differences.push(match orientation {
+                                Orientation::Horizontal => (index + d, j),
+                                Orientation::Vertical => (j, index + d),
+                            });
+                        }
+                    }
+                    if differences.len() > k {
+                        break;
+                    }
+                }
+                if differences.len() == k {
+                    reflections.push(Reflection {
+                        orientation,
+                        index,
+                        differences,
+                    });
+                }
             }
         }
+        reflections
+    }
+}
 
-        unimplemented!()
+impl Input {
+    pub fn mirrors(&self) -> &[Mirror] {
+        &self.mirrors
     }
 }
 
@@ -95,10 +99,16 @@ pub fn part1(input: &Input) -> usize {
     input
         .mirrors
         .iter()
-        .map(|mirror| mirror.reflections(Axis(0)) * 100 + mirror.reflections(Axis(1)))
+        .flat_map(|mirror| mirror.reflections_with_tolerance(0))
+        .map(|reflection| reflection.summary())
         .sum()
 }
 
 pub fn part2(input: &Input) -> usize {
-    input.mirrors.iter().map(|mirror| mirror.smudge()).sum()
+    input
+        .mirrors
+        .iter()
+        .flat_map(|mirror| mirror.reflections_with_tolerance(1))
+        .map(|reflection| reflection.summary())
+        .sum()
 }
@@ -1,140 +1,409 @@
-use std::collections::HashMap;
+use crate::cycle::{fast_forward, FastForward};
+use crate::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct Input {
-    map: Map,
+    platform: Platform,
 }
 
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
-        let map = Map::from(s);
-        Self { map }
+        let platform = Platform::from(s);
+        Self { platform }
     }
 }
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq)]
-struct Map {
-    map: Vec<Vec<char>>,
-    width: usize,
-    height: usize,
+impl Input {
+    pub fn platform(&self) -> &Platform {
+        &self.platform
+    }
 }
 
-impl From<&str> for Map {
-    fn from(s: &str) -> Self {
-        let map: Vec<Vec<_>> = s.lines().map(|line| line.chars().collect()).collect();
-        let width = map[0].len();
-        let height = map.len();
-        Self { map, width, height }
-    }
-}
-
-impl Map {
-    fn tilt_north(&mut self) {
-        for x in 0..self.width {
-            let mut cursor = 0;
-            for y in 0..self.height {
-                match self.map[y][x] {
-                    'O' => {
-                        self.map[y][x] = '.';
-                        self.map[cursor][x] = 'O';
-                        cursor += 1;
-                    }
-                    '#' => cursor = y + 1,
-                    _ => {}
-                }
-            }
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+impl TryFrom<char> for Direction {
+    type Error = Error;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        match c {
+            'N' => Ok(Direction::North),
+            'W' => Ok(Direction::West),
+            'S' => Ok(Direction::South),
+            'E' => Ok(Direction::East),
+            c => Err(Error::Logic(format!("invalid tilt direction: {}", c))),
         }
     }
+}
 
-    fn tilt_west(&mut self) {
-        for y in 0..self.height {
-            let mut cursor = 0;
-            for x in 0..self.width {
-                match self.map[y][x] {
-                    'O' => {
-                        self.map[y][x] = '.';
-                        self.map[y][cursor] = 'O';
-                        cursor += 1;
-                    }
-                    '#' => cursor = x + 1,
-                    _ => {}
+pub fn parse_sequence(s: &str) -> Result<Vec<Direction>, Error> {
+    s.chars().map(Direction::try_from).collect()
+}
+
+fn low_bits(n: usize) -> u64 {
+    if n >= 64 {
+        !0
+    } else {
+        (1 << n) - 1
+    }
+}
+
+// Transposes a 64x64 bit block, bit `c` of word `r` ending up as bit `r` of
+// word `c`, by swapping ever smaller off-diagonal sub-blocks.
+fn transpose64(a: &mut [u64; 64]) {
+    let mut j = 32;
+    let mut m: u64 = 0x0000_0000_FFFF_FFFF;
+    while j != 0 {
+        let mut k = 0;
+        while k < 64 {
+            let t = ((a[k] >> j) ^ a[k + j]) & m;
+            a[k] ^= t << j;
+            a[k + j] ^= t;
+            k = (k + j + 1) & !j;
+        }
+        j >>= 1;
+        m ^= m << j;
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct Bits(Vec<u64>);
+
+impl Bits {
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    // Moves all bits in the segment against one end of it.
+    fn pack(&mut self, segment: &Segment, towards_start: bool) {
+        let Segment { start, end, mask } = *segment;
+        if mask != 0 {
+            let (lo, len) = (start % 64, end - start);
+            let word = &mut self.0[start / 64];
+            let n = (*word & mask).count_ones() as usize;
+            let packed = if towards_start {
+                low_bits(n) << lo
+            } else {
+                mask ^ (low_bits(len - n) << lo)
+            };
+            *word = (*word & !mask) | packed;
+            return;
+        }
+
+        let (first, last) = (start / 64, (end - 1) / 64);
+        let mask = |w: usize, from: usize, to: usize| {
+            let lo = from.max(w * 64).min(w * 64 + 64) - w * 64;
+            let hi = to.max(w * 64).min(w * 64 + 64) - w * 64;
+            low_bits(hi - lo) << lo.min(63)
+        };
+        let n: usize = (first..=last)
+            .map(|w| (self.0[w] & mask(w, start, end)).count_ones() as usize)
+            .sum();
+        let (from, to) = if towards_start {
+            (start, start + n)
+        } else {
+            (end - n, end)
+        };
+        for w in first..=last {
+            self.0[w] = (self.0[w] & !mask(w, start, end)) | mask(w, from, to);
+        }
+    }
+
+    fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(w, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let b = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(w * 64 + b)
+            })
+        })
+    }
+
+    // Transposes a bitset of `lines` lines of `words` words each, with
+    // `lines` being a multiple of 64, into `words * 64` lines.
+    fn transpose(&self, lines: usize, words: usize) -> Self {
+        let mut out = vec![0; self.0.len()];
+        let out_words = lines / 64;
+        let mut block = [0; 64];
+        for by in 0..out_words {
+            for bx in 0..words {
+                for (r, word) in block.iter_mut().enumerate() {
+                    *word = self.0[(by * 64 + r) * words + bx];
+                }
+                transpose64(&mut block);
+                for (c, &word) in block.iter().enumerate() {
+                    out[(bx * 64 + c) * out_words + by] = word;
                 }
             }
         }
+        Self(out)
     }
+}
 
-    fn tilt_south(&mut self) {
-        for x in 0..self.width {
-            let mut cursor = self.height - 1;
-            for y in (0..self.height).rev() {
-                match self.map[y][x] {
-                    'O' => {
-                        self.map[y][x] = '.';
-                        self.map[cursor][x] = 'O';
-                        cursor = cursor.saturating_sub(1);
-                    }
-                    '#' => cursor = y.saturating_sub(1),
+// A run of cells between cube rocks, with `mask` selecting it within its
+// word, or zero if it spans multiple words.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+struct Segment {
+    start: usize,
+    end: usize,
+    mask: u64,
+}
+
+impl Segment {
+    fn new(start: usize, end: usize) -> Self {
+        let mask = if start / 64 == (end - 1) / 64 {
+            low_bits(end - start) << (start % 64)
+        } else {
+            0
+        };
+        Self { start, end, mask }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+enum Layout {
+    Rows,
+    Cols,
+}
+
+// The fixed part of a platform. The cube rocks never move and split each
+// line into segments, and lines are padded to whole words and their amount
+// to a multiple of 64 so layouts can be swapped by transposing 64x64 blocks.
+#[derive(Debug, PartialEq, Eq)]
+struct Grid {
+    cube: Bits,
+    row_segments: Vec<Segment>,
+    col_segments: Vec<Segment>,
+    width: usize,
+    height: usize,
+}
+
+impl Grid {
+    fn row_words(&self) -> usize {
+        self.width.div_ceil(64)
+    }
+
+    fn col_words(&self) -> usize {
+        self.height.div_ceil(64)
+    }
+}
+
+// Round rocks are a bitset laid out either row by row or column by column,
+// whichever the last tilt ran along. This is all that changes between tilts,
+// so it is what cycle detection hashes and keeps.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct Rocks {
+    round: Bits,
+    layout: Layout,
+}
+
+impl Rocks {
+    fn transpose(&mut self, grid: &Grid) {
+        let (row_words, col_words) = (grid.row_words(), grid.col_words());
+        (self.round, self.layout) = match self.layout {
+            Layout::Rows => (
+                self.round.transpose(col_words * 64, row_words),
+                Layout::Cols,
+            ),
+            Layout::Cols => (
+                self.round.transpose(row_words * 64, col_words),
+                Layout::Rows,
+            ),
+        };
+    }
+
+    fn rows(&self, grid: &Grid) -> Bits {
+        match self.layout {
+            Layout::Rows => self.round.clone(),
+            Layout::Cols => self
+                .round
+                .transpose(grid.row_words() * 64, grid.col_words()),
+        }
+    }
+
+    // A tilt just counts the rocks per segment and packs them against one end.
+    fn tilt(&mut self, grid: &Grid, direction: Direction) {
+        let (layout, towards_start) = match direction {
+            Direction::North => (Layout::Cols, true),
+            Direction::South => (Layout::Cols, false),
+            Direction::West => (Layout::Rows, true),
+            Direction::East => (Layout::Rows, false),
+        };
+        if self.layout != layout {
+            self.transpose(grid);
+        }
+
+        let segments = match layout {
+            Layout::Rows => &grid.row_segments,
+            Layout::Cols => &grid.col_segments,
+        };
+        for segment in segments {
+            self.round.pack(segment, towards_start);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Platform {
+    grid: Rc<Grid>,
+    rocks: Rocks,
+}
+
+impl From<&str> for Platform {
+    fn from(s: &str) -> Self {
+        let height = s.lines().count();
+        let width = s.lines().next().map_or(0, |line| line.len());
+        let row_bits = width.div_ceil(64) * 64;
+        let col_bits = height.div_ceil(64) * 64;
+
+        let mut round = Bits(vec![0; row_bits * col_bits / 64]);
+        let mut cube = round.clone();
+        for (y, line) in s.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    'O' => round.set(y * row_bits + x),
+                    '#' => cube.set(y * row_bits + x),
                     _ => {}
                 }
             }
         }
-    }
 
-    fn tilt_east(&mut self) {
-        for y in 0..self.height {
-            let mut cursor = self.width - 1;
-            for x in (0..self.width).rev() {
-                match self.map[y][x] {
-                    'O' => {
-                        self.map[y][x] = '.';
-                        self.map[y][cursor] = 'O';
-                        cursor = cursor.saturating_sub(1);
+        let segments =
+            |lines: usize, len: usize, bits: usize, index: &dyn Fn(usize, usize) -> usize| {
+                let mut segments = Vec::new();
+                for line in 0..lines {
+                    let mut start = 0;
+                    for i in 0..=len {
+                        if i == len || cube.get(index(line, i)) {
+                            if start < i {
+                                segments.push(Segment::new(line * bits + start, line * bits + i));
+                            }
+                            start = i + 1;
+                        }
                     }
-                    '#' => cursor = x.saturating_sub(1),
-                    _ => {}
                 }
-            }
+                segments
+            };
+        let row_segments = segments(height, width, row_bits, &|y, x| y * row_bits + x);
+        let col_segments = segments(width, height, col_bits, &|x, y| y * row_bits + x);
+
+        Self {
+            grid: Rc::new(Grid {
+                cube,
+                row_segments,
+                col_segments,
+                width,
+                height,
+            }),
+            rocks: Rocks {
+                round,
+                layout: Layout::Rows,
+            },
         }
     }
+}
 
-    fn load(&self) -> usize {
-        let mut summa = 0;
-        for x in 0..self.width {
-            for y in 0..self.height {
-                if self.map[y][x] == 'O' {
-                    summa += self.height - y;
-                }
+impl Platform {
+    pub fn tilt(&mut self, direction: Direction) {
+        self.rocks.tilt(&self.grid, direction);
+    }
+
+    pub fn cycle(&mut self, sequence: &[Direction]) {
+        for &direction in sequence {
+            self.tilt(direction);
+        }
+    }
+
+    pub fn load(&self) -> usize {
+        let row_bits = self.grid.row_words() * 64;
+        self.rocks
+            .rows(&self.grid)
+            .ones()
+            .map(|i| self.grid.height - i / row_bits)
+            .sum()
+    }
+
+    // Fast-forwards over the rocks alone, sharing the grid with the result.
+    pub fn after(&self, sequence: &[Direction], n: usize) -> FastForward<Platform> {
+        let step = |rocks: &Rocks| {
+            let mut rocks = rocks.clone();
+            for &direction in sequence {
+                rocks.tilt(&self.grid, direction);
             }
+            rocks
+        };
+        let FastForward { state, cycle } = fast_forward(self.rocks.clone(), step, n);
+        FastForward {
+            state: Platform {
+                grid: self.grid.clone(),
+                rocks: state,
+            },
+            cycle,
         }
-        summa
     }
+}
 
-    fn cycle(&mut self) {
-        self.tilt_north();
-        self.tilt_west();
-        self.tilt_south();
-        self.tilt_east();
+// Platforms sharing a grid only differ in their rocks, which are enough to hash.
+impl Hash for Platform {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rocks.hash(state);
     }
 }
 
-pub fn part1(input: &Input) -> usize {
-    let mut map = input.map.clone();
-    map.tilt_north();
-    map.load()
+impl PartialEq for Platform {
+    fn eq(&self, other: &Self) -> bool {
+        self.rocks == other.rocks
+            && (Rc::ptr_eq(&self.grid, &other.grid) || self.grid == other.grid)
+    }
 }
 
-pub fn part2(input: &Input) -> usize {
-    let mut map = input.map.clone();
-    let mut sequence = Vec::new();
-    let mut states = HashMap::new();
-    for i in 0.. {
-        map.cycle();
-        sequence.push(map.clone());
-        if let Some(n) = states.insert(map.clone(), i) {
-            let period = i - n;
-            let result = (1_000_000_000 - n) % period + n - 1;
-            return sequence[result].load();
+impl Eq for Platform {}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let round = self.rocks.rows(&self.grid);
+        let row_bits = self.grid.row_words() * 64;
+        for y in 0..self.grid.height {
+            for x in 0..self.grid.width {
+                let i = y * row_bits + x;
+                let c = if round.get(i) {
+                    'O'
+                } else if self.grid.cube.get(i) {
+                    '#'
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f)?;
         }
+        Ok(())
     }
-    0
+}
+
+pub fn part1(input: &Input) -> usize {
+    let mut platform = input.platform.clone();
+    platform.tilt(Direction::North);
+    platform.load()
+}
+
+pub fn part2(input: &Input) -> usize {
+    let sequence = parse_sequence("NWSE").unwrap();
+    input.platform.after(&sequence, 1_000_000_000).state.load()
 }
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use crate::Error;
+use std::fmt;
 
 #[derive(Debug)]
 pub struct Input {
@@ -8,11 +9,24 @@ pub struct Input {
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
         Self {
-            steps: s.split(',').map(|kv| kv.as_bytes().to_vec()).collect(),
+            steps: s
+                .trim()
+                .split(',')
+                .map(|kv| kv.as_bytes().to_vec())
+                .collect(),
         }
     }
 }
 
+impl Input {
+    pub fn operations(&self) -> Result<Vec<Operation>, Error> {
+        self.steps
+            .iter()
+            .map(|step| Operation::try_from(step.as_slice()))
+            .collect()
+    }
+}
+
 fn digest(bytes: &[u8]) -> usize {
     let mut hash = 0;
     for &b in bytes {
@@ -23,51 +37,157 @@ fn digest(bytes: &[u8]) -> usize {
     hash
 }
 
-pub fn part1(input: &Input) -> usize {
-    input.steps.iter().map(|step| digest(step.as_slice())).sum()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Insert(String, usize),
+    Remove(String),
 }
 
-pub fn part2(input: &Input) -> usize {
-    let mut hashmap = HashMap::new();
-    for step in &input.steps {
-        let (id, cmd) = if step[step.len() - 1].is_ascii_digit() {
-            step.as_slice().split_at(step.len() - 2)
-        } else {
-            step.as_slice().split_at(step.len() - 1)
-        };
-        let hash = digest(id);
-        let id: String = std::str::from_utf8(id).unwrap().to_string();
-        match cmd[0] {
-            b'=' => {
-                let value = (cmd[1] - b'0') as usize;
-                let lense: &mut Vec<(String, usize)> = hashmap.entry(hash).or_insert(Vec::new());
-                if let Some(item) = lense.iter_mut().find(|item| item.0 == id) {
-                    item.1 = value;
-                } else {
-                    lense.push((id, value));
-                }
+impl TryFrom<&[u8]> for Operation {
+    type Error = Error;
+
+    fn try_from(step: &[u8]) -> Result<Self, Self::Error> {
+        let step = std::str::from_utf8(step)
+            .map_err(|_| Error::Logic("step is not valid utf-8".to_string()))?;
+        let invalid = || Error::Logic(format!("invalid step: {:?}", step));
+        if let Some(label) = step.strip_suffix('-') {
+            if label.is_empty() {
+                return Err(invalid());
             }
-            b'-' => {
-                let lense: &mut Vec<(String, usize)> = hashmap.entry(hash).or_insert(Vec::new());
-                for i in 0..lense.len() {
-                    if lense[i].0 == id {
-                        lense.remove(i);
-                        break;
-                    }
-                }
+            Ok(Operation::Remove(label.to_string()))
+        } else if let Some((label, value)) = step.split_once('=') {
+            let value = value.parse().map_err(|_| invalid())?;
+            if label.is_empty() {
+                return Err(invalid());
             }
-            c => unreachable!("invalid command: {}", c),
+            Ok(Operation::Insert(label.to_string(), value))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Insert(label, value) => write!(f, "{}={}", label, value),
+            Operation::Remove(label) => write!(f, "{}-", label),
+        }
+    }
+}
+
+// The Holiday ASCII String Helper Manual Arrangement Procedure, 256 boxes of
+// lenses where each box keeps its lenses in insertion order.
+#[derive(Clone, Debug)]
+pub struct LensMap {
+    boxes: Vec<Vec<(String, usize)>>,
+}
+
+impl Default for LensMap {
+    fn default() -> Self {
+        Self {
+            boxes: vec![Vec::new(); 256],
         }
     }
+}
+
+impl LensMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, label: &str, value: usize) -> Option<usize> {
+        let lenses = &mut self.boxes[digest(label.as_bytes())];
+        if let Some(item) = lenses.iter_mut().find(|item| item.0 == label) {
+            Some(std::mem::replace(&mut item.1, value))
+        } else {
+            lenses.push((label.to_string(), value));
+            None
+        }
+    }
+
+    pub fn remove(&mut self, label: &str) -> Option<usize> {
+        let lenses = &mut self.boxes[digest(label.as_bytes())];
+        let slot = lenses.iter().position(|item| item.0 == label)?;
+        Some(lenses.remove(slot).1)
+    }
+
+    pub fn get(&self, label: &str) -> Option<usize> {
+        self.boxes[digest(label.as_bytes())]
+            .iter()
+            .find(|item| item.0 == label)
+            .map(|item| item.1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.boxes.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.iter().all(Vec::is_empty)
+    }
 
-    hashmap
-        .iter()
-        .map(|(boxn, lense)| {
-            lense
+    // Lenses as `(box, slot, label, value)`, in box and then slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &str, usize)> {
+        self.boxes.iter().enumerate().flat_map(|(boxn, lenses)| {
+            lenses
                 .iter()
                 .enumerate()
-                .map(|(slot, (_id, value))| (boxn + 1) * (slot + 1) * value)
-                .sum::<usize>()
+                .map(move |(slot, (label, value))| (boxn, slot, label.as_str(), *value))
         })
-        .sum()
+    }
+
+    pub fn apply(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Insert(label, value) => {
+                self.insert(label, *value);
+            }
+            Operation::Remove(label) => {
+                self.remove(label);
+            }
+        }
+    }
+
+    pub fn trace(&mut self, operations: &[Operation], out: &mut impl fmt::Write) -> fmt::Result {
+        for operation in operations {
+            self.apply(operation);
+            writeln!(out, "After \"{}\":", operation)?;
+            writeln!(out, "{}", self)?;
+        }
+        Ok(())
+    }
+
+    pub fn focusing_power(&self) -> usize {
+        self.iter()
+            .map(|(boxn, slot, _label, value)| (boxn + 1) * (slot + 1) * value)
+            .sum()
+    }
+}
+
+impl fmt::Display for LensMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (boxn, lenses) in self.boxes.iter().enumerate() {
+            if lenses.is_empty() {
+                continue;
+            }
+            write!(f, "Box {}:", boxn)?;
+            for (label, value) in lenses {
+                write!(f, " [{} {}]", label, value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn part1(input: &Input) -> usize {
+    input.steps.iter().map(|step| digest(step.as_slice())).sum()
+}
+
+pub fn part2(input: &Input) -> usize {
+    let mut lenses = LensMap::new();
+    for operation in &input.operations().unwrap() {
+        lenses.apply(operation);
+    }
+    lenses.focusing_power()
 }
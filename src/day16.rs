@@ -1,5 +1,6 @@
+use crate::animation::{Animation, Swatch};
 use ndarray::Array2;
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct Input {
@@ -17,7 +18,7 @@ impl From<&str> for Input {
 }
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
-enum Direction {
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -43,82 +44,497 @@ impl Coord {
     }
 }
 
-fn energized(map: &Array2<char>, initial: Coord, dir: Direction) -> usize {
-    let mut lights: VecDeque<_> = VecDeque::from([(initial, dir)]);
+static DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+impl Direction {
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn reverse(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    pub fn turn_left(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Left,
+            Direction::Down => Direction::Right,
+            Direction::Left => Direction::Down,
+            Direction::Right => Direction::Up,
+        }
+    }
+
+    pub fn turn_right(self) -> Direction {
+        self.turn_left().reverse()
+    }
+}
+
+// Table of tile to outgoing beam directions, indexed by incoming direction.
+// Tiles missing from the table let beams pass straight through, and with
+// `wrap` the beams leaving the map re-enter on the opposite edge.
+#[derive(Clone, Debug)]
+pub struct Optics {
+    table: HashMap<char, [Vec<Direction>; 4]>,
+    wrap: bool,
+}
+
+impl Default for Optics {
+    fn default() -> Self {
+        Self::empty()
+            .element('|', |dir| match dir {
+                Direction::Left | Direction::Right => vec![Direction::Up, Direction::Down],
+                dir => vec![dir],
+            })
+            .element('-', |dir| match dir {
+                Direction::Up | Direction::Down => vec![Direction::Left, Direction::Right],
+                dir => vec![dir],
+            })
+            .element('/', |dir| match dir {
+                Direction::Up | Direction::Down => vec![dir.turn_right()],
+                Direction::Left | Direction::Right => vec![dir.turn_left()],
+            })
+            .element('\\', |dir| match dir {
+                Direction::Up | Direction::Down => vec![dir.turn_left()],
+                Direction::Left | Direction::Right => vec![dir.turn_right()],
+            })
+    }
+}
+
+impl Optics {
+    pub fn empty() -> Self {
+        Self {
+            table: HashMap::new(),
+            wrap: false,
+        }
+    }
+
+    pub fn element(mut self, tile: char, rule: impl Fn(Direction) -> Vec<Direction>) -> Self {
+        self.table.insert(tile, DIRECTIONS.map(rule));
+        self
+    }
+
+    // Lets beams travelling in `dir` pass and absorbs all others.
+    pub fn one_way(self, tile: char, dir: Direction) -> Self {
+        self.element(tile, move |d| if d == dir { vec![d] } else { vec![] })
+    }
+
+    pub fn absorber(self, tile: char) -> Self {
+        self.element(tile, |_| vec![])
+    }
+
+    // Emits beams straight ahead and to either side.
+    pub fn triple_splitter(self, tile: char) -> Self {
+        self.element(tile, |dir| vec![dir.turn_left(), dir, dir.turn_right()])
+    }
+
+    pub fn wrapping(mut self, wrap: bool) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn deflect(&self, tile: char, dir: Direction) -> &[Direction] {
+        match self.table.get(&tile) {
+            Some(outputs) => &outputs[dir.index()],
+            None => std::slice::from_ref(&DIRECTIONS[dir.index()]),
+        }
+    }
+
+    fn next(&self, coord: Coord, dir: Direction, shape: &[usize]) -> Option<Coord> {
+        if !self.wrap {
+            return coord.next(dir, shape);
+        }
+        let (y, x) = coord.0;
+        let (height, width) = (shape[0], shape[1]);
+        Some(Coord(match dir {
+            Direction::Up => ((y + height - 1) % height, x),
+            Direction::Down => ((y + 1) % height, x),
+            Direction::Left => (y, (x + width - 1) % width),
+            Direction::Right => (y, (x + 1) % width),
+        }))
+    }
+}
+
+// Advances all beams one tile per step, handing the beams of every step and
+// the cells energized so far to `observe`.
+fn propagate(
+    map: &Array2<char>,
+    optics: &Optics,
+    initial: Coord,
+    dir: Direction,
+    mut observe: impl FnMut(&[(Coord, Direction)], &HashSet<(usize, usize)>),
+) -> usize {
+    let mut lights = vec![(initial, dir)];
     let mut visited = HashSet::new();
     let shape = map.shape();
     let mut energized = HashSet::new();
-    while let Some((coord, dir)) = lights.pop_front() {
-        energized.insert(coord.0);
-        if !visited.insert((coord, dir)) {
-            continue;
+    while !lights.is_empty() {
+        lights.retain(|&light| visited.insert(light));
+        energized.extend(lights.iter().map(|(coord, _)| coord.0));
+        if lights.is_empty() {
+            break;
         }
+        observe(&lights, &energized);
 
-        match (map[coord.0], dir) {
-            ('.' | '|', Direction::Up | Direction::Down) => {
-                if let Some(coord) = coord.next(dir, shape) {
-                    lights.push_back((coord, dir));
+        let mut next = Vec::new();
+        for (coord, dir) in lights {
+            for &dir in optics.deflect(map[coord.0], dir) {
+                if let Some(coord) = optics.next(coord, dir, shape) {
+                    next.push((coord, dir));
                 }
             }
-            ('.' | '-', Direction::Left | Direction::Right) => {
-                if let Some(coord) = coord.next(dir, shape) {
-                    lights.push_back((coord, dir));
-                }
+        }
+        lights = next;
+    }
+    energized.len()
+}
+
+fn energized(map: &Array2<char>, optics: &Optics, initial: Coord, dir: Direction) -> usize {
+    propagate(map, optics, initial, dir, |_, _| {})
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Mask(Vec<u64>);
+
+impl Mask {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn set(&mut self, i: usize) {
+        self.0[i / 64] |= 1 << (i % 64);
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn union(&mut self, other: &Mask) {
+        self.0.iter_mut().zip(&other.0).for_each(|(a, b)| *a |= b);
+    }
+
+    fn count(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+// A stretch of beam that follows a single path from its start state until it
+// splits, leaves the map or repeats itself.
+#[derive(Debug)]
+struct Segment {
+    cells: Vec<usize>,
+    next: Vec<usize>,
+}
+
+// Beam segments form a graph where the outputs of a splitter are shared by
+// every segment running into it. Collapsing the graph into strongly connected
+// components gives a DAG, where each component's energized cells are its own
+// plus those of the components after it, computed once for all entry points.
+#[derive(Debug)]
+pub struct Contraption<'m> {
+    map: &'m Array2<char>,
+    optics: Optics,
+    segments: Vec<Segment>,
+    lookup: HashMap<(Coord, Direction), usize>,
+    masks: Vec<Mask>,
+    component: Vec<usize>,
+}
+
+impl<'m> Contraption<'m> {
+    fn new(map: &'m Array2<char>, optics: Optics) -> Self {
+        let mut contraption = Self {
+            map,
+            optics,
+            segments: Vec::new(),
+            lookup: HashMap::new(),
+            masks: Vec::new(),
+            component: Vec::new(),
+        };
+        // Segments are traced off a work list rather than recursively, since a
+        // large map can chain far more segments than the stack has room for.
+        let mut pending = Vec::new();
+        for (coord, dir) in entries(map) {
+            contraption.intern(coord, dir, &mut pending);
+        }
+        while let Some((id, coord, dir)) = pending.pop() {
+            let (cells, splits) = contraption.trace(coord, dir);
+            let next = splits
+                .into_iter()
+                .map(|(coord, dir)| contraption.intern(coord, dir, &mut pending))
+                .collect();
+            contraption.segments[id] = Segment { cells, next };
+        }
+        contraption.condense();
+        contraption
+    }
+
+    // Returns the id of the segment starting at a state, reserving one and
+    // queueing it for tracing if it is new.
+    fn intern(
+        &mut self,
+        start: Coord,
+        dir: Direction,
+        pending: &mut Vec<(usize, Coord, Direction)>,
+    ) -> usize {
+        if let Some(&id) = self.lookup.get(&(start, dir)) {
+            return id;
+        }
+        let id = self.segments.len();
+        self.lookup.insert((start, dir), id);
+        self.segments.push(Segment {
+            cells: Vec::new(),
+            next: Vec::new(),
+        });
+        pending.push((id, start, dir));
+        id
+    }
+
+    // Follows a beam from a state, returning the cells it crosses and the
+    // states it splits into.
+    fn trace(&self, start: Coord, dir: Direction) -> (Vec<usize>, Vec<(Coord, Direction)>) {
+        let shape = self.map.shape();
+        let mut cells = Vec::new();
+        let mut splits = Vec::new();
+        let mut visited = HashSet::new();
+        let mut state = Some((start, dir));
+        while let Some((coord, dir)) = state.take() {
+            if !visited.insert((coord, dir)) {
+                break;
             }
-            ('|', Direction::Left | Direction::Right) => {
-                if let Some(coord) = coord.next(Direction::Up, shape) {
-                    lights.push_back((coord, Direction::Up));
-                }
-                if let Some(coord) = coord.next(Direction::Down, shape) {
-                    lights.push_back((coord, Direction::Down));
-                }
+            cells.push(coord.0 .0 * shape[1] + coord.0 .1);
+            let deflected = self.optics.deflect(self.map[coord.0], dir);
+            let outputs: Vec<_> = deflected
+                .iter()
+                .filter_map(|&dir| {
+                    self.optics
+                        .next(coord, dir, shape)
+                        .map(|coord| (coord, dir))
+                })
+                .collect();
+            match outputs[..] {
+                [output] if deflected.len() == 1 => state = Some(output),
+                _ => splits = outputs,
             }
-            ('-', Direction::Up | Direction::Down) => {
-                if let Some(coord) = coord.next(Direction::Left, shape) {
-                    lights.push_back((coord, Direction::Left));
-                }
-                if let Some(coord) = coord.next(Direction::Right, shape) {
-                    lights.push_back((coord, Direction::Right));
-                }
+        }
+        (cells, splits)
+    }
+
+    // Tarjan's algorithm, which emits components in reverse topological order
+    // so every successor's mask is complete before it is merged. The depth
+    // first search keeps its own stack of segments and their next edge.
+    fn condense(&mut self) {
+        struct Tarjan<'s> {
+            segments: &'s [Segment],
+            index: Vec<Option<usize>>,
+            lowlink: Vec<usize>,
+            stack: Vec<usize>,
+            on_stack: Vec<bool>,
+            counter: usize,
+            component: Vec<usize>,
+            masks: Vec<Mask>,
+            len: usize,
+        }
+
+        impl Tarjan<'_> {
+            fn enter(&mut self, v: usize) {
+                self.index[v] = Some(self.counter);
+                self.lowlink[v] = self.counter;
+                self.counter += 1;
+                self.stack.push(v);
+                self.on_stack[v] = true;
             }
-            ('/', Direction::Up) | ('\\', Direction::Down) => {
-                if let Some(coord) = coord.next(Direction::Right, shape) {
-                    lights.push_back((coord, Direction::Right));
+
+            fn visit(&mut self, root: usize) {
+                self.enter(root);
+                let mut frames = vec![(root, 0)];
+                while let Some(&mut (v, ref mut edge)) = frames.last_mut() {
+                    if let Some(&w) = self.segments[v].next.get(*edge) {
+                        *edge += 1;
+                        match self.index[w] {
+                            None => {
+                                self.enter(w);
+                                frames.push((w, 0));
+                            }
+                            Some(index) if self.on_stack[w] => {
+                                self.lowlink[v] = self.lowlink[v].min(index);
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+
+                    frames.pop();
+                    if let Some(&(parent, _)) = frames.last() {
+                        self.lowlink[parent] = self.lowlink[parent].min(self.lowlink[v]);
+                    }
+                    if Some(self.lowlink[v]) == self.index[v] {
+                        self.emit(v);
+                    }
                 }
             }
-            ('/', Direction::Down) | ('\\', Direction::Up) => {
-                if let Some(coord) = coord.next(Direction::Left, shape) {
-                    lights.push_back((coord, Direction::Left));
+
+            fn emit(&mut self, v: usize) {
+                let id = self.masks.len();
+                let mut mask = Mask::new(self.len);
+                let mut members = Vec::new();
+                loop {
+                    let w = self.stack.pop().unwrap();
+                    self.on_stack[w] = false;
+                    self.component[w] = id;
+                    members.push(w);
+                    if w == v {
+                        break;
+                    }
                 }
-            }
-            ('/', Direction::Left) | ('\\', Direction::Right) => {
-                if let Some(coord) = coord.next(Direction::Down, shape) {
-                    lights.push_back((coord, Direction::Down));
+                for &w in &members {
+                    self.segments[w].cells.iter().for_each(|&i| mask.set(i));
+                    for &next in &self.segments[w].next {
+                        if self.component[next] != id {
+                            mask.union(&self.masks[self.component[next]]);
+                        }
+                    }
                 }
+                self.masks.push(mask);
             }
-            ('/', Direction::Right) | ('\\', Direction::Left) => {
-                if let Some(coord) = coord.next(Direction::Up, shape) {
-                    lights.push_back((coord, Direction::Up));
-                }
+        }
+
+        let n = self.segments.len();
+        let mut tarjan = Tarjan {
+            segments: &self.segments,
+            index: vec![None; n],
+            lowlink: vec![0; n],
+            stack: Vec::new(),
+            on_stack: vec![false; n],
+            counter: 0,
+            component: vec![usize::MAX; n],
+            masks: Vec::new(),
+            len: self.map.len(),
+        };
+        for v in 0..n {
+            if tarjan.index[v].is_none() {
+                tarjan.visit(v);
             }
-            _ => {}
+        }
+        self.component = tarjan.component;
+        self.masks = tarjan.masks;
+    }
+
+    // Only states entering the map from its edge, or leaving a splitter, start
+    // a segment, so any other state has no mask of its own.
+    fn mask(&self, coord: Coord, dir: Direction) -> Option<&Mask> {
+        let id = self.lookup.get(&(coord, dir))?;
+        Some(&self.masks[self.component[*id]])
+    }
+
+    pub fn energized(&self, coord: (usize, usize), dir: Direction) -> Option<usize> {
+        self.mask(Coord(coord), dir).map(Mask::count)
+    }
+
+    pub fn best(&self) -> Best {
+        let (coord, dir) = entries(self.map)
+            .max_by_key(|&(coord, dir)| self.mask(coord, dir).map_or(0, Mask::count))
+            .unwrap();
+        let mask = self.mask(coord, dir).unwrap();
+        let width = self.map.shape()[1];
+        Best {
+            entry: (coord.0, dir),
+            energized: mask.count(),
+            mask: Array2::from_shape_fn(self.map.dim(), |(y, x)| mask.get(y * width + x)),
         }
     }
-    energized.len()
+}
+
+#[derive(Debug)]
+pub struct Best {
+    pub entry: ((usize, usize), Direction),
+    pub energized: usize,
+    pub mask: Array2<bool>,
+}
+
+fn entries(map: &Array2<char>) -> impl Iterator<Item = (Coord, Direction)> {
+    let (height, width) = map.dim();
+    (0..height)
+        .map(|y| (Coord((y, 0)), Direction::Right))
+        .chain((0..height).map(move |y| (Coord((y, width - 1)), Direction::Left)))
+        .chain((0..width).map(|x| (Coord((0, x)), Direction::Down)))
+        .chain((0..width).map(move |x| (Coord((height - 1, x)), Direction::Up)))
+}
+
+impl Input {
+    pub fn contraption(&self) -> Contraption<'_> {
+        Contraption::new(&self.map, Optics::default())
+    }
+
+    pub fn contraption_with(&self, optics: Optics) -> Contraption<'_> {
+        Contraption::new(&self.map, optics)
+    }
+
+    // One frame per step, with the beam heads drawn over the energized cells
+    // drawn over the contraption itself.
+    pub fn animate(&self, optics: &Optics, start: (usize, usize), dir: Direction) -> Animation {
+        let mut tiles: Vec<char> = self.map.iter().copied().collect();
+        tiles.sort_unstable();
+        tiles.dedup();
+        let mut palette: Vec<_> = tiles
+            .iter()
+            .map(|&glyph| Swatch {
+                color: if glyph == '.' {
+                    [0, 0, 0]
+                } else {
+                    [96, 96, 96]
+                },
+                glyph,
+            })
+            .collect();
+        let (energized, head) = (palette.len() as u8, palette.len() as u8 + 1);
+        palette.push(Swatch {
+            color: [255, 200, 0],
+            glyph: '#',
+        });
+        palette.push(Swatch {
+            color: [255, 40, 40],
+            glyph: '*',
+        });
+
+        let (height, width) = self.map.dim();
+        let background: Vec<u8> = self
+            .map
+            .iter()
+            .map(|glyph| tiles.binary_search(glyph).unwrap() as u8)
+            .collect();
+        let mut animation = Animation::new(width, height, palette);
+        propagate(&self.map, optics, Coord(start), dir, |lights, cells| {
+            let mut frame = background.clone();
+            for &(y, x) in cells {
+                frame[y * width + x] = energized;
+            }
+            for (coord, _) in lights {
+                frame[coord.0 .0 * width + coord.0 .1] = head;
+            }
+            animation.push(frame);
+        });
+        animation
+    }
 }
 
 pub fn part1(input: &Input) -> usize {
-    energized(&input.map, Coord((0, 0)), Direction::Right)
+    energized(
+        &input.map,
+        &Optics::default(),
+        Coord((0, 0)),
+        Direction::Right,
+    )
 }
 
 pub fn part2(input: &Input) -> usize {
-    let shape = input.map.shape();
-    (0..shape[0])
-        .map(|y| (Coord((y, 0)), Direction::Right))
-        .chain((0..shape[0]).map(|y| (Coord((y, shape[1] - 1)), Direction::Left)))
-        .chain((0..shape[1]).map(|x| (Coord((0, x)), Direction::Down)))
-        .chain((0..shape[1]).map(|x| (Coord((shape[0] - 1, x)), Direction::Up)))
-        .map(|(coord, dir)| energized(&input.map, coord, dir))
-        .max()
-        .unwrap()
+    input.contraption().best().energized
 }
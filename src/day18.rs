@@ -1,3 +1,5 @@
+use crate::image::Image;
+use crate::Error;
 use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
@@ -12,15 +14,16 @@ impl From<&str> for Input {
             .lines()
             .map(|line| {
                 let mut tokens = line.split_ascii_whitespace();
-                let step1 = Step {
-                    dir: tokens.next().unwrap().into(),
-                    len: tokens.next().unwrap().parse().unwrap(),
-                };
+                let dir = tokens.next().unwrap().into();
+                let len = tokens.next().unwrap().parse().unwrap();
 
                 let tail = tokens.next().unwrap();
+                let color = [2, 4, 6].map(|i| u8::from_str_radix(&tail[i..i + 2], 16).unwrap());
+                let step1 = Step { dir, len, color };
                 let step2 = Step {
                     dir: tail[7..8].into(),
                     len: isize::from_str_radix(&tail[2..7], 16).unwrap(),
+                    color,
                 };
                 (step1, step2)
             })
@@ -30,7 +33,7 @@ impl From<&str> for Input {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Direction {
+pub enum Direction {
     Up,
     Down,
     Left,
@@ -49,10 +52,32 @@ impl From<&str> for Direction {
     }
 }
 
+impl Direction {
+    fn reverse(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
-struct Step {
-    dir: Direction,
-    len: isize,
+pub struct Step {
+    pub dir: Direction,
+    pub len: isize,
+    pub color: [u8; 3],
+}
+
+impl Input {
+    pub fn steps(&self) -> &[Step] {
+        &self.steps1
+    }
+
+    pub fn hex_steps(&self) -> &[Step] {
+        &self.steps2
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -183,10 +208,171 @@ fn interior(steps: &[Step]) -> usize {
     summa
 }
 
+fn vertices(steps: &[Step]) -> Result<Vec<(isize, isize)>, Error> {
+    if steps.is_empty() {
+        return Err(Error::Logic("dig plan is empty".to_string()));
+    }
+    let mut pos = (0, 0);
+    let mut vertices = vec![pos];
+    for step in steps {
+        if step.len <= 0 {
+            return Err(Error::Logic(format!("step {:?} has no length", step)));
+        }
+        pos = match step.dir {
+            Direction::Up => (pos.0, pos.1 - step.len),
+            Direction::Down => (pos.0, pos.1 + step.len),
+            Direction::Left => (pos.0 - step.len, pos.1),
+            Direction::Right => (pos.0 + step.len, pos.1),
+        };
+        vertices.push(pos);
+    }
+    if pos != (0, 0) {
+        return Err(Error::Logic(format!(
+            "dig plan does not close, it ends at {:?}",
+            pos
+        )));
+    }
+    vertices.pop();
+    Ok(vertices)
+}
+
+// Trenches may only meet where one step turns into the next, anything else
+// makes the outline cross or touch itself.
+fn check_simple(steps: &[Step], vertices: &[(isize, isize)]) -> Result<(), Error> {
+    let n = steps.len();
+    let bounds = |i: usize| {
+        let (a, b) = (vertices[i], vertices[(i + 1) % n]);
+        (a.0.min(b.0), a.0.max(b.0), a.1.min(b.1), a.1.max(b.1))
+    };
+    for i in 0..n {
+        let (x0, x1, y0, y1) = bounds(i);
+        for j in i + 1..n {
+            let touches = if j == i + 1 || (i == 0 && j == n - 1) {
+                steps[i].dir == steps[j].dir.reverse()
+            } else {
+                let (u0, u1, v0, v1) = bounds(j);
+                x0 <= u1 && u0 <= x1 && y0 <= v1 && v0 <= y1
+            };
+            if touches {
+                return Err(Error::Logic(format!(
+                    "dig plan intersects itself at steps {} and {}",
+                    i, j
+                )));
+            }
+        }
+    }
+    if twice_signed_area(vertices) == 0 {
+        return Err(Error::Logic("dig plan encloses no area".to_string()));
+    }
+    Ok(())
+}
+
+// Shoelace formula for the area enclosed by the trench centres, positive for
+// clockwise outlines as y grows downwards.
+fn twice_signed_area(vertices: &[(isize, isize)]) -> isize {
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .map(|(&(x0, y0), &(x1, y1))| x0 * y1 - x1 * y0)
+        .sum()
+}
+
+pub fn lagoon(steps: &[Step]) -> Result<usize, Error> {
+    let vertices = vertices(steps)?;
+    check_simple(steps, &vertices)?;
+    let twice_area = twice_signed_area(&vertices).unsigned_abs();
+    let boundary: usize = steps.iter().map(|step| step.len as usize).sum();
+    // Pick's theorem, A = I + B/2 - 1, gives the interior and the trench is
+    // dug out on top of it.
+    let interior = (twice_area + 2 - boundary) / 2;
+    Ok(interior + boundary)
+}
+
+// Draws the plan with each cube of trench in the color of its step on top of
+// the filled interior. Plans larger than `max_size` in either direction are
+// scaled down so that every pixel covers a square of cubes, sampled at its
+// centre for the interior.
+pub fn render(steps: &[Step], max_size: usize) -> Result<Image, Error> {
+    const OUTSIDE: [u8; 3] = [0, 0, 0];
+    const INSIDE: [u8; 3] = [96, 96, 96];
+
+    let vertices = vertices(steps)?;
+    let x0 = vertices.iter().map(|v| v.0).min().unwrap_or(0);
+    let y0 = vertices.iter().map(|v| v.1).min().unwrap_or(0);
+    let width = vertices.iter().map(|v| v.0 - x0).max().unwrap_or(0) as usize + 1;
+    let height = vertices.iter().map(|v| v.1 - y0).max().unwrap_or(0) as usize + 1;
+    let scale = width.max(height).div_ceil(max_size.max(1));
+    let mut image = Image::new(width.div_ceil(scale), height.div_ceil(scale), OUTSIDE);
+
+    let n = vertices.len();
+    let verticals: Vec<_> = (0..n)
+        .map(|i| (vertices[i], vertices[(i + 1) % n]))
+        .filter(|(a, b)| a.0 == b.0)
+        .map(|(a, b)| (a.0 - x0, a.1.min(b.1) - y0, a.1.max(b.1) - y0))
+        .collect();
+    for py in 0..image.height() {
+        let y = (py * scale + scale / 2) as isize;
+        let mut crossings: Vec<_> = verticals
+            .iter()
+            .filter(|&&(_, top, bottom)| top <= y && y < bottom)
+            .map(|&(x, _, _)| x)
+            .collect();
+        crossings.sort_unstable();
+        for span in crossings.chunks_exact(2) {
+            let from = span[0] as usize / scale;
+            let to = (span[1] as usize / scale + 1).min(image.width());
+            for px in from..to {
+                let x = px * scale + scale / 2;
+                if span[0] as usize <= x && x <= span[1] as usize {
+                    image.set(px, py, INSIDE);
+                }
+            }
+        }
+    }
+
+    for (i, step) in steps.iter().enumerate() {
+        let (a, b) = (vertices[i], vertices[(i + 1) % n]);
+        let (xa, xb) = (((a.0 - x0) as usize) / scale, ((b.0 - x0) as usize) / scale);
+        let (ya, yb) = (((a.1 - y0) as usize) / scale, ((b.1 - y0) as usize) / scale);
+        for px in xa.min(xb)..=xa.max(xb) {
+            for py in ya.min(yb)..=ya.max(yb) {
+                image.set(px, py, step.color);
+            }
+        }
+    }
+    Ok(image)
+}
+
+// The row sweep expects a clockwise outline, so counter-clockwise plans are
+// walked backwards.
+fn cross_check(steps: &[Step], volume: usize) {
+    if cfg!(debug_assertions) {
+        let vertices = vertices(steps).unwrap();
+        let expected = if twice_signed_area(&vertices) > 0 {
+            interior(steps)
+        } else {
+            let reversed: Vec<_> = steps
+                .iter()
+                .rev()
+                .map(|step| Step {
+                    dir: step.dir.reverse(),
+                    ..*step
+                })
+                .collect();
+            interior(&reversed)
+        };
+        assert_eq!(volume, expected, "shoelace and row sweep disagree");
+    }
+}
+
 pub fn part1(input: &Input) -> usize {
-    interior(&input.steps1)
+    let volume = lagoon(&input.steps1).unwrap();
+    cross_check(&input.steps1, volume);
+    volume
 }
 
 pub fn part2(input: &Input) -> usize {
-    interior(&input.steps2)
+    let volume = lagoon(&input.steps2).unwrap();
+    cross_check(&input.steps2, volume);
+    volume
 }
@@ -1,10 +1,13 @@
 #![allow(clippy::single_range_in_vec_init)]
 
-use std::collections::HashMap;
+use crate::Error;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::ops::Range;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Input {
+    categories: Vec<String>,
     workflows: HashMap<String, Workflow>,
     ratings: Vec<Rating>,
 }
@@ -12,104 +15,838 @@ pub struct Input {
 impl From<&str> for Input {
     fn from(s: &str) -> Self {
         let s = s.replace("\r\n", "\n");
-        let mut sections = s.split("\n\n");
-        let workflows = sections
-            .next()
-            .unwrap()
+        let (workflows, ratings) = s.split_once("\n\n").unwrap_or((&s, ""));
+
+        // Categories are named by the ratings, in order of appearance, with
+        // any only mentioned by the workflows following after.
+        let ratings: Vec<Vec<(&str, usize)>> = ratings
+            .lines()
+            .map(|line| parse_rating(line).unwrap())
+            .collect();
+        let mut categories = Categories(Vec::new());
+        for rating in &ratings {
+            for (name, _) in rating {
+                categories.index(name);
+            }
+        }
+
+        let workflows = workflows
             .lines()
             .map(|line| {
                 let delim = line.find('{').unwrap();
                 let id = line[0..delim].to_string();
-                let workflow = Workflow::from(&line[delim + 1..line.len() - 1]);
+                let body = line[delim + 1..].strip_suffix('}').unwrap();
+                let workflow = Workflow::parse(body, &mut categories).unwrap();
                 (id, workflow)
             })
             .collect();
-        let ratings = sections.next().unwrap().lines().map(Rating::from).collect();
 
-        Self { workflows, ratings }
+        let categories = categories.0;
+        let ratings = ratings
+            .into_iter()
+            .map(|rating| Rating::new(&categories, &rating).unwrap())
+            .collect();
+        Self {
+            categories,
+            workflows,
+            ratings,
+        }
     }
 }
 
-#[derive(Debug)]
-struct Workflow {
-    rules: Vec<(Rule, Result)>,
-    or_else: Result,
+// Workflows in the puzzle's syntax, starting with `in`, followed by the ratings.
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.workflows.keys().collect();
+        names.sort_unstable_by_key(|&name| (name != "in", name));
+        for name in names {
+            let workflow = &self.workflows[name];
+            write!(f, "{}{{", name)?;
+            for (rule, result) in &workflow.rules {
+                rule.write(f, &self.categories, false)?;
+                write!(f, ":{},", result)?;
+            }
+            writeln!(f, "{}}}", workflow.or_else)?;
+        }
+        writeln!(f)?;
+        for rating in &self.ratings {
+            let fields: Vec<_> = self
+                .categories
+                .iter()
+                .zip(&rating.values)
+                .map(|(category, value)| format!("{}={}", category, value))
+                .collect();
+            writeln!(f, "{{{}}}", fields.join(","))?;
+        }
+        Ok(())
+    }
 }
 
-impl Workflow {
-    fn run(&self, rating: &Rating) -> Result {
-        for (rule, result) in &self.rules {
-            match rule {
-                Rule::Greater(part, n) => {
-                    if rating.part(part) > *n {
-                        return result.clone();
+impl Input {
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    pub fn compile(&self, bounds: Range<usize>) -> std::result::Result<DecisionTable, Error> {
+        let rating = SuperRating::new(self.categories.len(), bounds);
+        let mut out = Vec::new();
+        accepted(&self.workflows, "in", rating, &mut Vec::new(), &mut out)?;
+        Ok(DecisionTable::new(self.categories.clone(), out))
+    }
+
+    // Analyzes the workflows for ratings within `bounds`, following only the
+    // rules that can fire.
+    pub fn lint(&self, bounds: Range<usize>) -> Vec<Lint> {
+        let mut names: Vec<&str> = self.workflows.keys().map(String::as_str).collect();
+        names.sort_unstable();
+
+        let mut lints = Vec::new();
+        if !self.workflows.contains_key("in") {
+            lints.push(Lint::MissingEntry);
+        }
+
+        let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut live_branches = HashMap::new();
+        for &name in &names {
+            let workflow = &self.workflows[name];
+            let rating = SuperRating::new(self.categories.len(), bounds.clone());
+            let live = workflow.live(rating);
+            for (rule, &live) in live[..workflow.rules.len()].iter().enumerate() {
+                if !live {
+                    lints.push(Lint::DeadRule {
+                        workflow: name.to_string(),
+                        rule,
+                    });
+                }
+            }
+            if !live[workflow.rules.len()] {
+                lints.push(Lint::DeadFallback(name.to_string()));
+            }
+
+            let mut targets = Vec::new();
+            for (result, &live) in workflow.branches().zip(&live) {
+                if let Result::Next(id) = result {
+                    if !live {
+                        continue;
+                    }
+                    if !self.workflows.contains_key(id) {
+                        lints.push(Lint::MissingTarget {
+                            workflow: name.to_string(),
+                            target: id.clone(),
+                        });
+                    } else if !targets.contains(&id.as_str()) {
+                        targets.push(id.as_str());
                     }
                 }
-                Rule::Less(part, n) => {
-                    if rating.part(part) < *n {
-                        return result.clone();
+            }
+            edges.insert(name, targets);
+            live_branches.insert(name, live);
+        }
+
+        let reach = |from: &str| {
+            let mut seen = HashSet::new();
+            let mut stack: Vec<&str> = edges[from].clone();
+            while let Some(curr) = stack.pop() {
+                if seen.insert(curr) {
+                    stack.extend(&edges[curr]);
+                }
+            }
+            seen
+        };
+        let reaches: HashMap<&str, HashSet<&str>> =
+            names.iter().map(|&name| (name, reach(name))).collect();
+
+        // Workflows that can reach themselves when each is looked at on its own.
+        // Whether ratings actually go round depends on the ranges narrowed on
+        // the way, which only following them from "in" tells.
+        let cyclic: HashSet<&str> = names
+            .iter()
+            .copied()
+            .filter(|&name| reaches[name].contains(name))
+            .collect();
+
+        if self.workflows.contains_key("in") {
+            let rating = SuperRating::new(self.categories.len(), bounds.clone());
+            let mut cycles = Vec::new();
+            find_cycles(&self.workflows, "in", rating, &mut Vec::new(), &mut cycles);
+            lints.extend(cycles.into_iter().map(Lint::Cycle));
+
+            let reachable = &reaches["in"];
+            for &name in &names {
+                if name != "in" && !reachable.contains(name) {
+                    lints.push(Lint::Unreachable(name.to_string()));
+                }
+            }
+        }
+
+        // Workflows whose live branches all end up at the same place, either
+        // accepting, rejecting or handing over to one other workflow.
+        let mut constant: HashMap<&str, Option<Result>> = HashMap::new();
+        fn resolve<'w>(
+            name: &'w str,
+            workflows: &'w HashMap<String, Workflow>,
+            live: &HashMap<&str, Vec<bool>>,
+            cyclic: &HashSet<&str>,
+            constant: &mut HashMap<&'w str, Option<Result>>,
+        ) -> Option<Result> {
+            if let Some(result) = constant.get(name) {
+                return result.clone();
+            }
+            let mut outcomes = Vec::new();
+            for (result, _) in workflows[name]
+                .branches()
+                .zip(&live[name])
+                .filter(|(_, &live)| live)
+            {
+                let outcome = match result {
+                    Result::Next(id)
+                        if workflows.contains_key(id) && !cyclic.contains(id.as_str()) =>
+                    {
+                        match resolve(id, workflows, live, cyclic, constant) {
+                            Some(Result::Next(_)) | None => result.clone(),
+                            Some(terminal) => terminal,
+                        }
                     }
+                    _ => result.clone(),
+                };
+                if !outcomes.contains(&outcome) {
+                    outcomes.push(outcome);
                 }
             }
+            let result = match &outcomes[..] {
+                [outcome] => Some(outcome.clone()),
+                _ => None,
+            };
+            constant.insert(name, result.clone());
+            result
         }
-        self.or_else.clone()
+        for &name in &names {
+            if cyclic.contains(name) {
+                continue;
+            }
+            if let Some(result) = resolve(
+                name,
+                &self.workflows,
+                &live_branches,
+                &cyclic,
+                &mut constant,
+            ) {
+                lints.push(Lint::Constant {
+                    workflow: name.to_string(),
+                    result: result.to_string(),
+                });
+            }
+        }
+
+        lints
+    }
+
+    pub fn check(&self, bounds: Range<usize>) -> std::result::Result<(), Error> {
+        check_lints(&self.lint(bounds))
+    }
+
+    // Rewrites the workflows into an equivalent smaller set for ratings within
+    // `bounds`, checked by counting the accepted combinations before and after.
+    pub fn optimize(&self, bounds: Range<usize>) -> std::result::Result<Input, Error> {
+        self.check(bounds.clone())?;
+        let mut optimized = self.clone();
+        loop {
+            let before = optimized.to_string();
+            optimized.optimize_step(&bounds);
+            if optimized.to_string() == before {
+                break;
+            }
+        }
+
+        let (expected, actual) = (
+            self.combinations(bounds.clone())?,
+            optimized.combinations(bounds)?,
+        );
+        if expected != actual {
+            return Err(Error::Logic(format!(
+                "optimized workflows accept {} combinations instead of {}",
+                actual, expected
+            )));
+        }
+        Ok(optimized)
+    }
+
+    fn optimize_step(&mut self, bounds: &Range<usize>) {
+        let rating = SuperRating::new(self.categories.len(), bounds.clone());
+        for workflow in self.workflows.values_mut() {
+            *workflow = workflow.simplify(rating.clone());
+        }
+
+        let folded: HashMap<String, Result> = self
+            .lint(bounds.clone())
+            .into_iter()
+            .filter_map(|lint| match lint {
+                Lint::Constant { workflow, result } if workflow != "in" => {
+                    Some((workflow, Result::from(result.as_str())))
+                }
+                _ => None,
+            })
+            .collect();
+        let fold = |result: &mut Result| {
+            if let Result::Next(id) = result {
+                if let Some(folded) = folded.get(id) {
+                    *result = folded.clone();
+                }
+            }
+        };
+        for workflow in self.workflows.values_mut() {
+            workflow
+                .rules
+                .iter_mut()
+                .for_each(|(_, result)| fold(result));
+            fold(&mut workflow.or_else);
+        }
+
+        let mut reachable = HashSet::from(["in".to_string()]);
+        let mut stack = vec!["in".to_string()];
+        while let Some(curr) = stack.pop() {
+            for result in self.workflows[&curr].branches() {
+                if let Result::Next(id) = result {
+                    if reachable.insert(id.clone()) {
+                        stack.push(id.clone());
+                    }
+                }
+            }
+        }
+        self.workflows.retain(|name, _| reachable.contains(name));
+    }
+
+    // Number of accepted ratings with every category within `bounds`.
+    pub fn combinations(&self, bounds: Range<usize>) -> std::result::Result<u128, Error> {
+        Ok(self.compile(bounds)?.combinations())
     }
 }
 
-impl From<&str> for Workflow {
-    fn from(s: &str) -> Self {
-        let rules_inclusive: Vec<_> = s.split(',').collect();
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lint {
+    MissingEntry,
+    MissingTarget { workflow: String, target: String },
+    Cycle(Vec<String>),
+    Unreachable(String),
+    DeadRule { workflow: String, rule: usize },
+    DeadFallback(String),
+    Constant { workflow: String, result: String },
+}
+
+impl Lint {
+    // Errors make the workflows impossible to run, the rest are warnings.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            Lint::MissingEntry | Lint::MissingTarget { .. } | Lint::Cycle(_)
+        )
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::MissingEntry => write!(f, "there is no \"in\" workflow"),
+            Lint::MissingTarget { workflow, target } => {
+                write!(
+                    f,
+                    "{} sends ratings to missing workflow {}",
+                    workflow, target
+                )
+            }
+            Lint::Cycle(workflows) => write!(f, "workflows {} form a cycle", workflows.join(", ")),
+            Lint::Unreachable(workflow) => write!(f, "{} is never reached", workflow),
+            Lint::DeadRule { workflow, rule } => {
+                write!(f, "rule {} of {} can never fire", rule + 1, workflow)
+            }
+            Lint::DeadFallback(workflow) => write!(f, "fallback of {} can never fire", workflow),
+            Lint::Constant { workflow, result } => {
+                write!(
+                    f,
+                    "{} always resolves to {} and can be inlined",
+                    workflow, result
+                )
+            }
+        }
+    }
+}
+
+fn check_lints(lints: &[Lint]) -> std::result::Result<(), Error> {
+    let errors: Vec<_> = lints
+        .iter()
+        .filter(|lint| lint.is_error())
+        .map(|lint| lint.to_string())
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Logic(errors.join(", ")))
+    }
+}
+
+struct Categories(Vec<String>);
+
+impl Categories {
+    fn index(&mut self, name: &str) -> usize {
+        match self.0.iter().position(|category| category == name) {
+            Some(i) => i,
+            None => {
+                self.0.push(name.to_string());
+                self.0.len() - 1
+            }
+        }
+    }
+}
+
+fn parse_rating(s: &str) -> std::result::Result<Vec<(&str, usize)>, Error> {
+    let invalid = || Error::Logic(format!("invalid rating: {}", s));
+    let inner = s
+        .strip_prefix('{')
+        .and_then(|s| s.strip_suffix('}'))
+        .ok_or_else(invalid)?;
+    inner
+        .split(',')
+        .map(|field| {
+            let (name, value) = field.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim().parse().map_err(|_| invalid())?;
+            Ok((name.trim(), value))
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct Workflow {
+    rules: Vec<(Rule, Result)>,
+    or_else: Result,
+}
+
+impl Workflow {
+    fn parse(s: &str, categories: &mut Categories) -> std::result::Result<Self, Error> {
+        let rules_inclusive: Vec<_> = s.split(',').map(str::trim).collect();
         let rules = rules_inclusive[..rules_inclusive.len() - 1]
             .iter()
             .map(|&rule| {
-                let (rule, result) = rule.split_once(':').unwrap();
-                (rule.into(), result.into())
+                let (rule, result) = rule
+                    .rsplit_once(':')
+                    .ok_or_else(|| Error::Logic(format!("rule without target: {}", rule)))?;
+                Ok((Rule::parse(rule, categories)?, result.trim().into()))
             })
-            .collect();
+            .collect::<std::result::Result<_, Error>>()?;
         let or_else = Result::from(*rules_inclusive.last().unwrap());
-        Self { rules, or_else }
+        Ok(Self { rules, or_else })
+    }
+
+    // Splits `rating` into the parts each rule, and then the fallback, sends
+    // on, in order.
+    fn route(&self, rating: SuperRating) -> Vec<(&Result, SuperRating)> {
+        let mut routes = Vec::new();
+        let mut ratings = vec![rating];
+        for (rule, result) in &self.rules {
+            let mut or_else = Vec::new();
+            for rating in ratings {
+                let (is_match, rest) = rating.split(rule);
+                routes.extend(is_match.into_iter().map(|rating| (result, rating)));
+                or_else.extend(rest);
+            }
+            ratings = or_else;
+        }
+        routes.extend(ratings.into_iter().map(|rating| (&self.or_else, rating)));
+        routes
+    }
+
+    fn branches(&self) -> impl Iterator<Item = &Result> {
+        self.rules
+            .iter()
+            .map(|(_, result)| result)
+            .chain(std::iter::once(&self.or_else))
+    }
+
+    // Whether each rule, followed by the fallback, can fire for some rating in
+    // `rating` that none of the earlier rules took.
+    fn live(&self, rating: SuperRating) -> Vec<bool> {
+        let mut remaining = vec![rating];
+        let mut live = Vec::new();
+        for (rule, _) in &self.rules {
+            let mut fires = false;
+            let mut or_else = Vec::new();
+            for rating in remaining {
+                let (is_match, rest) = rating.split(rule);
+                fires |= !is_match.is_empty();
+                or_else.extend(rest);
+            }
+            live.push(fires);
+            remaining = or_else;
+        }
+        live.push(!remaining.is_empty());
+        live
+    }
+
+    // Drops rules that never fire and conditions that always hold for the
+    // ratings reaching them, cuts the workflow short at a rule that always
+    // fires, and merges neighbouring rules with the same result.
+    fn simplify(&self, rating: SuperRating) -> Workflow {
+        let mut remaining = vec![rating];
+        let mut rules: Vec<(Rule, Result)> = Vec::new();
+        let mut or_else = self.or_else.clone();
+        for (rule, result) in &self.rules {
+            let rule = match rule.simplify(&remaining) {
+                Truth::Never => continue,
+                Truth::Always => {
+                    or_else = result.clone();
+                    break;
+                }
+                Truth::Rule(rule) => rule,
+            };
+            remaining = remaining
+                .iter()
+                .flat_map(|rating| rating.split(&rule).1)
+                .collect();
+            rules.push((rule, result.clone()));
+            if remaining.is_empty() {
+                break;
+            }
+        }
+        if remaining.is_empty() && rules.last().is_some_and(|(_, result)| *result != or_else) {
+            or_else = rules.pop().unwrap().1;
+        }
+        while rules.last().is_some_and(|(_, result)| *result == or_else) {
+            rules.pop();
+        }
+
+        let mut merged: Vec<(Rule, Result)> = Vec::new();
+        for (rule, result) in rules {
+            match merged.last_mut() {
+                Some((last, last_result)) if *last_result == result => {
+                    let lhs = std::mem::replace(last, Rule::Compare(0, Op::Less, 0));
+                    *last = Rule::Or(Box::new(lhs), Box::new(rule));
+                }
+                _ => merged.push((rule, result)),
+            }
+        }
+        Workflow {
+            rules: merged,
+            or_else,
+        }
+    }
+
+    fn run(&self, rating: &Rating) -> Result {
+        for (rule, result) in &self.rules {
+            if rule.eval(&rating.values) {
+                return result.clone();
+            }
+        }
+        self.or_else.clone()
     }
 }
 
-#[derive(Debug)]
-enum Part {
-    X,
-    M,
-    A,
-    S,
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Equal,
+    NotEqual,
 }
 
-impl From<&str> for Part {
-    fn from(s: &str) -> Self {
-        match s {
-            "x" => Part::X,
-            "m" => Part::M,
-            "a" => Part::A,
-            "s" => Part::S,
-            _ => unreachable!("invalid part: {}", s),
+impl Op {
+    fn eval(self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Op::Less => lhs < rhs,
+            Op::LessEq => lhs <= rhs,
+            Op::Greater => lhs > rhs,
+            Op::GreaterEq => lhs >= rhs,
+            Op::Equal => lhs == rhs,
+            Op::NotEqual => lhs != rhs,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Equal => "==",
+            Op::NotEqual => "!=",
+        }
+    }
+
+    fn negate(self) -> Op {
+        match self {
+            Op::Less => Op::GreaterEq,
+            Op::LessEq => Op::Greater,
+            Op::Greater => Op::LessEq,
+            Op::GreaterEq => Op::Less,
+            Op::Equal => Op::NotEqual,
+            Op::NotEqual => Op::Equal,
+        }
+    }
+
+    // Values satisfying `value op n`, as disjoint ascending ranges.
+    fn matching(self, n: usize) -> Vec<Range<usize>> {
+        match self {
+            Op::Less => vec![0..n],
+            Op::LessEq => vec![0..n + 1],
+            Op::Greater => vec![n + 1..usize::MAX],
+            Op::GreaterEq => vec![n..usize::MAX],
+            Op::Equal => vec![n..n + 1],
+            Op::NotEqual => vec![0..n, n + 1..usize::MAX],
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 enum Rule {
-    Less(Part, usize),
-    Greater(Part, usize),
+    Compare(usize, Op, usize),
+    And(Box<Rule>, Box<Rule>),
+    Or(Box<Rule>, Box<Rule>),
 }
 
-impl From<&str> for Rule {
-    fn from(s: &str) -> Self {
-        let part = Part::from(&s[0..1]);
-        let n = s[2..].parse().unwrap();
-        match &s[1..2] {
-            "<" => Self::Less(part, n),
-            ">" => Self::Greater(part, n),
-            op => unreachable!("invalid operator: {}", op),
+enum Truth {
+    Always,
+    Never,
+    Rule(Rule),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token<'s> {
+    Name(&'s str),
+    Number(usize),
+    Op(Op),
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> std::result::Result<Vec<Token<'_>>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(&rest[..len]));
+            len
+        } else if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let n = rest[..len]
+                .parse()
+                .map_err(|_| Error::Logic(format!("invalid number in rule: {}", s)))?;
+            tokens.push(Token::Number(n));
+            len
+        } else {
+            let (token, len) = match rest.get(..2) {
+                Some("<=") => (Token::Op(Op::LessEq), 2),
+                Some(">=") => (Token::Op(Op::GreaterEq), 2),
+                Some("==") => (Token::Op(Op::Equal), 2),
+                Some("!=") => (Token::Op(Op::NotEqual), 2),
+                Some("&&") => (Token::And, 2),
+                Some("||") => (Token::Or, 2),
+                _ => match c {
+                    '<' => (Token::Op(Op::Less), 1),
+                    '>' => (Token::Op(Op::Greater), 1),
+                    '(' => (Token::Open, 1),
+                    ')' => (Token::Close, 1),
+                    c => return Err(Error::Logic(format!("unexpected {:?} in rule: {}", c, s))),
+                },
+            };
+            tokens.push(token);
+            len
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// Recursive descent over `or := and ("||" and)*`, `and := atom ("&&" atom)*`
+// and `atom := "(" or ")" | name op number`.
+struct Parser<'t, 's> {
+    tokens: &'t [Token<'s>],
+    pos: usize,
+    categories: &'t mut Categories,
+}
+
+impl<'t, 's> Parser<'t, 's> {
+    fn next(&mut self) -> Option<&'t Token<'s>> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> std::result::Result<Rule, Error> {
+        let mut rule = self.and()?;
+        while self.tokens.get(self.pos) == Some(&Token::Or) {
+            self.pos += 1;
+            rule = Rule::Or(Box::new(rule), Box::new(self.and()?));
+        }
+        Ok(rule)
+    }
+
+    fn and(&mut self) -> std::result::Result<Rule, Error> {
+        let mut rule = self.atom()?;
+        while self.tokens.get(self.pos) == Some(&Token::And) {
+            self.pos += 1;
+            rule = Rule::And(Box::new(rule), Box::new(self.atom()?));
+        }
+        Ok(rule)
+    }
+
+    fn atom(&mut self) -> std::result::Result<Rule, Error> {
+        let unexpected = |token: Option<&Token>| match token {
+            Some(token) => Error::Logic(format!("unexpected {:?} in rule", token)),
+            None => Error::Logic("unexpected end of rule".to_string()),
+        };
+        match self.next() {
+            Some(Token::Open) => {
+                let rule = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(rule),
+                    token => Err(unexpected(token)),
+                }
+            }
+            Some(&Token::Name(name)) => {
+                let category = self.categories.index(name);
+                let op = match self.next() {
+                    Some(&Token::Op(op)) => op,
+                    token => return Err(unexpected(token)),
+                };
+                match self.next() {
+                    Some(&Token::Number(n)) => Ok(Rule::Compare(category, op, n)),
+                    token => Err(unexpected(token)),
+                }
+            }
+            token => Err(unexpected(token)),
         }
     }
 }
 
-#[derive(Clone, Debug)]
+impl Rule {
+    fn parse(s: &str, categories: &mut Categories) -> std::result::Result<Self, Error> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            categories,
+        };
+        let rule = parser.or()?;
+        if parser.pos != tokens.len() {
+            return Err(Error::Logic(format!("trailing input in rule: {}", s)));
+        }
+        Ok(rule)
+    }
+
+    fn simplify(&self, ratings: &[SuperRating]) -> Truth {
+        let (mut matches, mut fails) = (false, false);
+        for rating in ratings {
+            let (is_match, or_else) = rating.split(self);
+            matches |= !is_match.is_empty();
+            fails |= !or_else.is_empty();
+        }
+        match (matches, fails) {
+            (false, _) => return Truth::Never,
+            (true, false) => return Truth::Always,
+            _ => {}
+        }
+
+        // Each operand is simplified for the ratings it actually sees, and the
+        // left one is dropped when the right one already implies it.
+        let partition = |ratings: &[SuperRating], rule: &Rule, matching: bool| {
+            ratings
+                .iter()
+                .flat_map(|rating| {
+                    let (is_match, or_else) = rating.split(rule);
+                    if matching {
+                        is_match
+                    } else {
+                        or_else
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Rule::Compare(..) => Truth::Rule(self.clone()),
+            Rule::And(lhs, rhs) => {
+                let lhs = match lhs.simplify(ratings) {
+                    Truth::Rule(lhs) => lhs,
+                    Truth::Always => return rhs.simplify(ratings),
+                    Truth::Never => return Truth::Never,
+                };
+                let rhs = match rhs.simplify(&partition(ratings, &lhs, true)) {
+                    Truth::Rule(rhs) => rhs,
+                    Truth::Always => return Truth::Rule(lhs),
+                    Truth::Never => return Truth::Never,
+                };
+                match lhs.simplify(&partition(ratings, &rhs, true)) {
+                    Truth::Always => Truth::Rule(rhs),
+                    _ => Truth::Rule(Rule::And(Box::new(lhs), Box::new(rhs))),
+                }
+            }
+            Rule::Or(lhs, rhs) => {
+                let lhs = match lhs.simplify(ratings) {
+                    Truth::Rule(lhs) => lhs,
+                    Truth::Always => return Truth::Always,
+                    Truth::Never => return rhs.simplify(ratings),
+                };
+                let rhs = match rhs.simplify(&partition(ratings, &lhs, false)) {
+                    Truth::Rule(rhs) => rhs,
+                    Truth::Always => return Truth::Always,
+                    Truth::Never => return Truth::Rule(lhs),
+                };
+                match lhs.simplify(&partition(ratings, &rhs, false)) {
+                    Truth::Never => Truth::Rule(rhs),
+                    _ => Truth::Rule(Rule::Or(Box::new(lhs), Box::new(rhs))),
+                }
+            }
+        }
+    }
+
+    // Writes the rule back in workflow syntax, with parentheses only around
+    // alternatives nested in a conjunction.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        categories: &[String],
+        nested: bool,
+    ) -> fmt::Result {
+        match self {
+            Rule::Compare(category, op, n) => {
+                write!(f, "{}{}{}", categories[*category], op.symbol(), n)
+            }
+            Rule::And(lhs, rhs) => {
+                lhs.write(f, categories, true)?;
+                write!(f, " && ")?;
+                rhs.write(f, categories, true)
+            }
+            Rule::Or(lhs, rhs) => {
+                if nested {
+                    write!(f, "(")?;
+                }
+                lhs.write(f, categories, false)?;
+                write!(f, " || ")?;
+                rhs.write(f, categories, false)?;
+                if nested {
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn eval(&self, values: &[usize]) -> bool {
+        match self {
+            Rule::Compare(category, op, n) => op.eval(values[*category], *n),
+            Rule::And(lhs, rhs) => lhs.eval(values) && rhs.eval(values),
+            Rule::Or(lhs, rhs) => lhs.eval(values) || rhs.eval(values),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum Result {
     Accept,
     Reject,
@@ -126,26 +863,38 @@ impl From<&str> for Result {
     }
 }
 
-#[derive(Debug)]
+impl fmt::Display for Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Result::Accept => write!(f, "A"),
+            Result::Reject => write!(f, "R"),
+            Result::Next(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 struct Rating {
-    x: usize,
-    m: usize,
-    a: usize,
-    s: usize,
+    values: Vec<usize>,
 }
 
 impl Rating {
-    fn part(&self, part: &Part) -> usize {
-        match part {
-            Part::X => self.x,
-            Part::M => self.m,
-            Part::A => self.a,
-            Part::S => self.s,
-        }
+    fn new(categories: &[String], fields: &[(&str, usize)]) -> std::result::Result<Self, Error> {
+        let values = categories
+            .iter()
+            .map(|category| {
+                fields
+                    .iter()
+                    .find(|(name, _)| name == category)
+                    .map(|&(_, value)| value)
+                    .ok_or_else(|| Error::Logic(format!("rating has no {} value", category)))
+            })
+            .collect::<std::result::Result<_, Error>>()?;
+        Ok(Self { values })
     }
 
     fn value(&self) -> usize {
-        self.x + self.m + self.a + self.s
+        self.values.iter().sum()
     }
 
     fn is_accepted(&self, workflows: &HashMap<String, Workflow>) -> bool {
@@ -161,151 +910,298 @@ impl Rating {
     }
 }
 
-impl From<&str> for Rating {
-    fn from(s: &str) -> Self {
-        let mut token = s[1..s.len() - 1].split(',').map(|p| &p[2..]);
-        let x = token.next().unwrap().parse().unwrap();
-        let m = token.next().unwrap().parse().unwrap();
-        let a = token.next().unwrap().parse().unwrap();
-        let s = token.next().unwrap().parse().unwrap();
-        Self { x, m, a, s }
+fn intersect(ranges: &[Range<usize>], with: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut out = Vec::new();
+    for range in ranges {
+        for other in with {
+            let start = range.start.max(other.start);
+            let end = range.end.min(other.end);
+            if start < end {
+                out.push(start..end);
+            }
+        }
     }
+    out
 }
 
+// A set of ratings given by a list of disjoint ranges per category.
 #[derive(Clone, Debug)]
 struct SuperRating {
-    x: Vec<Range<usize>>,
-    m: Vec<Range<usize>>,
-    a: Vec<Range<usize>>,
-    s: Vec<Range<usize>>,
+    categories: Vec<Vec<Range<usize>>>,
 }
 
 impl SuperRating {
-    fn new() -> Self {
+    fn new(categories: usize, bounds: Range<usize>) -> Self {
         Self {
-            x: vec![1..4001],
-            m: vec![1..4001],
-            a: vec![1..4001],
-            s: vec![1..4001],
-        }
-    }
-
-    fn combinations(&self) -> usize {
-        self.x.iter().map(|r| r.len()).sum::<usize>()
-            * self.m.iter().map(|r| r.len()).sum::<usize>()
-            * self.a.iter().map(|r| r.len()).sum::<usize>()
-            * self.s.iter().map(|r| r.len()).sum::<usize>()
-    }
-
-    fn part(&self, part: &Part) -> &Vec<Range<usize>> {
-        match part {
-            Part::X => &self.x,
-            Part::M => &self.m,
-            Part::A => &self.a,
-            Part::S => &self.s,
+            categories: vec![vec![bounds]; categories],
         }
     }
 
-    fn part_mut(&mut self, part: &Part) -> &mut Vec<Range<usize>> {
-        match part {
-            Part::X => &mut self.x,
-            Part::M => &mut self.m,
-            Part::A => &mut self.a,
-            Part::S => &mut self.s,
-        }
+    fn is_empty(&self) -> bool {
+        self.categories.iter().any(|ranges| ranges.is_empty())
     }
 
-    fn split(&self, rule: &Rule) -> (Self, Self) {
-        let mut is_match = self.clone();
-        let mut or_else = self.clone();
-
+    // Splits into the ratings matching the rule and those that don't, each as
+    // disjoint super ratings, since compound rules need not give a single one.
+    fn split(&self, rule: &Rule) -> (Vec<Self>, Vec<Self>) {
         match rule {
-            Rule::Greater(part, n) => {
-                let ranges = self.part(part);
-                let succeed = is_match.part_mut(part);
-                let fail = or_else.part_mut(part);
-
-                succeed.clear();
-                fail.clear();
-                for range in ranges {
-                    if range.start > *n {
-                        succeed.push(range.clone());
-                    } else if range.end <= *n + 1 {
-                        fail.push(range.clone());
+            Rule::Compare(category, op, n) => {
+                let ranges = &self.categories[*category];
+                let mut is_match = self.clone();
+                let mut or_else = self.clone();
+                is_match.categories[*category] = intersect(ranges, &op.matching(*n));
+                or_else.categories[*category] = intersect(ranges, &op.negate().matching(*n));
+                let keep = |rating: SuperRating| {
+                    if rating.is_empty() {
+                        vec![]
                     } else {
-                        fail.push(range.start..*n + 1);
-                        succeed.push(*n + 1..range.end);
+                        vec![rating]
                     }
+                };
+                (keep(is_match), keep(or_else))
+            }
+            Rule::And(lhs, rhs) => {
+                let (lhs_match, mut or_else) = self.split(lhs);
+                let mut is_match = Vec::new();
+                for rating in lhs_match {
+                    let (both, rhs_else) = rating.split(rhs);
+                    is_match.extend(both);
+                    or_else.extend(rhs_else);
                 }
+                (is_match, or_else)
             }
-            Rule::Less(part, n) => {
-                let ranges = self.part(part);
-                let succeed = is_match.part_mut(part);
-                let fail = or_else.part_mut(part);
-
-                succeed.clear();
-                fail.clear();
-                for range in ranges {
-                    if range.end <= *n {
-                        succeed.push(range.clone());
-                    } else if range.start >= *n {
-                        fail.push(range.clone());
-                    } else {
-                        succeed.push(range.start..*n);
-                        fail.push(*n..range.end);
-                    }
+            Rule::Or(lhs, rhs) => {
+                let (mut is_match, lhs_else) = self.split(lhs);
+                let mut or_else = Vec::new();
+                for rating in lhs_else {
+                    let (rhs_match, neither) = rating.split(rhs);
+                    is_match.extend(rhs_match);
+                    or_else.extend(neither);
                 }
+                (is_match, or_else)
             }
         }
-
-        (is_match, or_else)
     }
 }
 
-fn combinations(
-    workflows: &HashMap<String, Workflow>,
-    curr: String,
-    mut rating: SuperRating,
-) -> usize {
-    let workflow = workflows.get(&curr).unwrap();
+// Follows the ratings from `curr` on, with `path` holding the workflows they
+// went through to get there. Ratings coming back to one of those would go
+// round forever.
+fn accepted<'w>(
+    workflows: &'w HashMap<String, Workflow>,
+    curr: &'w str,
+    rating: SuperRating,
+    path: &mut Vec<&'w str>,
+    out: &mut Vec<SuperRating>,
+) -> std::result::Result<(), Error> {
+    let workflow = workflows
+        .get(curr)
+        .ok_or_else(|| Error::Logic(format!("there is no workflow {}", curr)))?;
+    if path.contains(&curr) {
+        return Err(Error::Logic(format!(
+            "ratings loop through workflows {}",
+            path.join(", ")
+        )));
+    }
 
-    let mut summa = 0;
-    for (rule, result) in &workflow.rules {
-        let (is_match, or_else) = rating.split(rule);
+    path.push(curr);
+    for (result, rating) in workflow.route(rating) {
         match result {
-            Result::Accept => {
-                summa += is_match.combinations();
-            }
+            Result::Accept => out.push(rating),
             Result::Reject => {}
-            Result::Next(id) => {
-                summa += combinations(workflows, id.clone(), is_match);
+            Result::Next(id) => accepted(workflows, id, rating, path, out)?,
+        }
+    }
+    path.pop();
+    Ok(())
+}
+
+// Collects the workflows ratings from `curr` on can go round, each loop once
+// with its workflows sorted. Missing workflows are reported on their own.
+fn find_cycles<'w>(
+    workflows: &'w HashMap<String, Workflow>,
+    curr: &'w str,
+    rating: SuperRating,
+    path: &mut Vec<&'w str>,
+    cycles: &mut Vec<Vec<String>>,
+) {
+    path.push(curr);
+    for (result, rating) in workflows[curr].route(rating) {
+        let Result::Next(id) = result else {
+            continue;
+        };
+        if let Some(start) = path.iter().position(|&name| name == id) {
+            let mut cycle: Vec<String> = path[start..].iter().map(|s| s.to_string()).collect();
+            cycle.sort_unstable();
+            if !cycles.contains(&cycle) {
+                cycles.push(cycle);
             }
+        } else if workflows.contains_key(id) {
+            find_cycles(workflows, id, rating, path, cycles);
         }
-        rating = or_else;
     }
+    path.pop();
+}
+
+// A box of ratings with a single range per category.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hyperrectangle {
+    ranges: Vec<Range<usize>>,
+}
+
+impl Hyperrectangle {
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    pub fn contains(&self, values: &[usize]) -> bool {
+        self.ranges
+            .iter()
+            .zip(values)
+            .all(|(range, value)| range.contains(value))
+    }
+
+    pub fn volume(&self) -> u128 {
+        self.ranges.iter().map(|r| r.len() as u128).product()
+    }
+}
+
+// The accepted ratings as disjoint hyperrectangles, with everything outside
+// of them rejected. Lookups bisect the first category's boundaries and only
+// test the rectangles spanning that stretch.
+#[derive(Clone, Debug)]
+pub struct DecisionTable {
+    categories: Vec<String>,
+    rectangles: Vec<Hyperrectangle>,
+    bounds: Vec<usize>,
+    candidates: Vec<Vec<usize>>,
+}
+
+impl DecisionTable {
+    fn new(categories: Vec<String>, accepted: Vec<SuperRating>) -> Self {
+        let mut rectangles = Vec::new();
+        for rating in accepted {
+            let mut boxes = vec![Vec::new()];
+            for ranges in &rating.categories {
+                boxes = boxes
+                    .into_iter()
+                    .flat_map(|prefix: Vec<Range<usize>>| {
+                        ranges.iter().map(move |range| {
+                            let mut prefix = prefix.clone();
+                            prefix.push(range.clone());
+                            prefix
+                        })
+                    })
+                    .collect();
+            }
+            rectangles.extend(boxes.into_iter().map(|ranges| Hyperrectangle { ranges }));
+        }
+
+        let mut bounds: Vec<_> = rectangles
+            .iter()
+            .filter_map(|rectangle| rectangle.ranges.first())
+            .flat_map(|range| [range.start, range.end])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let candidates = bounds
+            .iter()
+            .map(|&start| {
+                (0..rectangles.len())
+                    .filter(|&i| {
+                        rectangles[i]
+                            .ranges
+                            .first()
+                            .is_none_or(|range| range.contains(&start))
+                    })
+                    .collect()
+            })
+            .collect();
 
-    match &workflow.or_else {
-        Result::Accept => {
-            summa += rating.combinations();
+        Self {
+            categories,
+            rectangles,
+            bounds,
+            candidates,
         }
-        Result::Reject => {}
-        Result::Next(id) => {
-            summa += combinations(workflows, id.clone(), rating.clone());
+    }
+
+    pub fn categories(&self) -> &[String] {
+        &self.categories
+    }
+
+    pub fn rectangles(&self) -> &[Hyperrectangle] {
+        &self.rectangles
+    }
+
+    pub fn combinations(&self) -> u128 {
+        self.rectangles.iter().map(Hyperrectangle::volume).sum()
+    }
+
+    // Values are given in the order of `categories`.
+    pub fn lookup(&self, values: &[usize]) -> bool {
+        let candidates = match values.first() {
+            Some(value) => match self.bounds.partition_point(|bound| bound <= value) {
+                0 => return false,
+                i => &self.candidates[i - 1],
+            },
+            None => return !self.rectangles.is_empty(),
+        };
+        candidates
+            .iter()
+            .any(|&i| self.rectangles[i].contains(values))
+    }
+}
+
+// A markdown table with a row of inclusive ranges per accepted hyperrectangle.
+impl fmt::Display for DecisionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "| {} |", self.categories.join(" | "))?;
+        writeln!(f, "|{}", "---|".repeat(self.categories.len()))?;
+        for rectangle in &self.rectangles {
+            let ranges: Vec<_> = rectangle
+                .ranges
+                .iter()
+                .map(|range| format!("{}..={}", range.start, range.end - 1))
+                .collect();
+            writeln!(f, "| {} |", ranges.join(" | "))?;
         }
+        Ok(())
     }
+}
 
-    summa
+// Both parts lint the workflows first, reporting every lint and refusing to
+// run workflows that one of them shows can't be.
+fn lint_before_solving(input: &Input, bounds: Range<usize>) {
+    let lints = input.lint(bounds);
+    for lint in &lints {
+        eprintln!("{}", lint);
+    }
+    if let Err(error) = check_lints(&lints) {
+        panic!("{}", error);
+    }
 }
 
 pub fn part1(input: &Input) -> usize {
+    lint_before_solving(input, 0..usize::MAX);
+    let table = input
+        .compile(0..usize::MAX)
+        .expect("linted workflows compile");
     input
         .ratings
         .iter()
-        .filter(|rating| rating.is_accepted(&input.workflows))
+        .filter(|rating| {
+            let accepted = table.lookup(&rating.values);
+            debug_assert_eq!(accepted, rating.is_accepted(&input.workflows));
+            accepted
+        })
         .map(|rating| rating.value())
         .sum()
 }
 
-pub fn part2(input: &Input) -> usize {
-    combinations(&input.workflows, "in".to_string(), SuperRating::new())
+pub fn part2(input: &Input) -> u128 {
+    lint_before_solving(input, 1..4001);
+    input
+        .combinations(1..4001)
+        .expect("linted workflows compile")
 }
@@ -1,6 +1,8 @@
+use crate::Error;
 use dyn_clone::DynClone;
 use std::collections::{HashMap, VecDeque};
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
+use std::io::{self, Write};
 
 #[derive(Debug)]
 pub struct Input {
@@ -50,14 +52,30 @@ impl From<&str> for Input {
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum Pulse {
+pub enum Pulse {
     High,
     Low,
 }
 
+impl fmt::Display for Pulse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pulse::High => write!(f, "high"),
+            Pulse::Low => write!(f, "low"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Broadcaster,
+    FlipFlop,
+    Conjunction,
+}
+
 trait Module: DynClone + Debug {
     fn process(&mut self, pulse: Pulse, from: &str) -> Option<Pulse>;
-    fn is_high(&self) -> bool;
+    fn kind(&self) -> Kind;
 }
 
 dyn_clone::clone_trait_object!(Module);
@@ -79,8 +97,8 @@ impl Module for Broadcaster {
         self.curr
     }
 
-    fn is_high(&self) -> bool {
-        unimplemented!()
+    fn kind(&self) -> Kind {
+        Kind::Broadcaster
     }
 }
 
@@ -110,8 +128,8 @@ impl Module for FlipFlop {
         }
     }
 
-    fn is_high(&self) -> bool {
-        unimplemented!()
+    fn kind(&self) -> Kind {
+        Kind::FlipFlop
     }
 }
 
@@ -146,8 +164,8 @@ impl Module for Conjunction {
         }
     }
 
-    fn is_high(&self) -> bool {
-        self.memory.values().all(|pulse| *pulse == Pulse::High)
+    fn kind(&self) -> Kind {
+        Kind::Conjunction
     }
 }
 
@@ -182,79 +200,470 @@ impl Machine {
     }
 }
 
-fn gcd(mut a: usize, mut b: usize) -> usize {
-    while b != 0 {
-        if a > b {
-            std::mem::swap(&mut a, &mut b);
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub press: usize,
+    pub seq: usize,
+    pub from: String,
+    pub pulse: Pulse,
+    pub to: String,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}: {} -{}-> {}",
+            self.press, self.seq, self.from, self.pulse, self.to
+        )
+    }
+}
+
+// Every pulse sent over a number of button presses, numbered by press from 1
+// and by their order within the press from 0.
+#[derive(Clone, Debug)]
+pub struct Trace {
+    modules: Vec<(String, Kind)>,
+    events: Vec<Event>,
+}
+
+impl Trace {
+    pub fn modules(&self) -> &[(String, Kind)] {
+        &self.modules
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    // Writes a waveform with every flip-flop and conjunction as a signal that
+    // is high while its last sent pulse was high, plus the press count, with
+    // one time unit per pulse.
+    pub fn write_vcd(&self, out: &mut impl Write) -> io::Result<()> {
+        let signals: Vec<_> = self
+            .modules
+            .iter()
+            .filter(|(_, kind)| *kind != Kind::Broadcaster)
+            .enumerate()
+            .map(|(i, (name, _))| (name.as_str(), vcd_id(i + 1)))
+            .collect();
+        let ids: HashMap<&str, &str> = signals
+            .iter()
+            .map(|(name, id)| (*name, id.as_str()))
+            .collect();
+        let press = vcd_id(0);
+
+        writeln!(out, "$timescale 1ns $end")?;
+        writeln!(out, "$scope module machine $end")?;
+        writeln!(out, "$var integer 32 {} press $end", press)?;
+        for (name, id) in &signals {
+            writeln!(out, "$var wire 1 {} {} $end", id, name)?;
         }
-        b %= a;
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+        writeln!(out, "#0")?;
+        writeln!(out, "$dumpvars")?;
+        writeln!(out, "b0 {}", press)?;
+        for (_, id) in &signals {
+            writeln!(out, "0{}", id)?;
+        }
+        writeln!(out, "$end")?;
+
+        let mut levels: HashMap<&str, Pulse> = HashMap::new();
+        for (time, event) in self.events.iter().enumerate() {
+            let mut changes = Vec::new();
+            if event.seq == 0 {
+                changes.push(format!("b{:b} {}", event.press, press));
+            }
+            if let Some(id) = ids.get(event.from.as_str()) {
+                let level = levels.entry(&event.from).or_insert(Pulse::Low);
+                if *level != event.pulse {
+                    *level = event.pulse;
+                    let bit = if event.pulse == Pulse::High { 1 } else { 0 };
+                    changes.push(format!("{}{}", bit, id));
+                }
+            }
+            if !changes.is_empty() {
+                writeln!(out, "#{}", time + 1)?;
+                for change in changes {
+                    writeln!(out, "{}", change)?;
+                }
+            }
+        }
+        writeln!(out, "#{}", self.events.len() + 1)
     }
-    a
 }
 
-fn lcm(a: usize, b: usize) -> usize {
-    a * b / gcd(a, b)
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
 }
 
-pub fn part1(input: &Input) -> usize {
-    let mut high = 0;
-    let mut low = 0;
+// Identifiers made of the printable characters VCD allows.
+fn vcd_id(mut n: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            return id;
+        }
+        n -= 1;
+    }
+}
 
-    let mut machine = input.machine.clone();
-    for _ in 1..=1000 {
-        machine.run(|_from, pulse, _to| {
-            // println!("{} -{:?}-> {}", _from, _pulse, _to);
-            match pulse {
-                Pulse::High => high += 1,
-                Pulse::Low => low += 1,
+impl Input {
+    pub fn trace(&self, presses: usize) -> Trace {
+        let mut modules: Vec<_> = self
+            .machine
+            .modules
+            .iter()
+            .map(|(name, module)| (name.clone(), module.kind()))
+            .collect();
+        modules.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut machine = self.machine.clone();
+        let mut events = Vec::new();
+        for press in 1..=presses {
+            let mut seq = 0;
+            machine.run(|from, pulse, to| {
+                events.push(Event {
+                    press,
+                    seq,
+                    from: from.clone(),
+                    pulse,
+                    to: to.clone(),
+                });
+                seq += 1;
+            });
+        }
+        Trace { modules, events }
+    }
+}
+
+// A ripple counter of flip-flops, least significant bit first, where the
+// bits set in `target` feed `hub`, which resets the counter once it fires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Counter {
+    pub bits: Vec<String>,
+    pub hub: String,
+    pub target: usize,
+}
+
+// The modules that only the broadcaster feeds and that only feed the sink
+// through `output`, which goes high in presses `phase + k * period` from
+// press `first` on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subcircuit {
+    pub output: String,
+    pub modules: Vec<String>,
+    pub counter: Option<Counter>,
+    pub first: usize,
+    pub period: usize,
+    pub phase: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    sink: String,
+    subcircuits: Vec<Subcircuit>,
+}
+
+impl Analysis {
+    // The conjunction feeding `rx`.
+    pub fn sink(&self) -> &str {
+        &self.sink
+    }
+
+    pub fn subcircuits(&self) -> &[Subcircuit] {
+        &self.subcircuits
+    }
+
+    // First press in which every sub-circuit sends a high pulse to the sink,
+    // which then sends a low pulse to `rx`.
+    pub fn presses(&self) -> Result<usize, Error> {
+        let (mut rem, mut modulus) = (0i128, 1i128);
+        for subcircuit in &self.subcircuits {
+            let (r, m) = (subcircuit.phase as i128, subcircuit.period as i128);
+            let (g, p, _) = extended_gcd(modulus, m);
+            if (r - rem) % g != 0 {
+                return Err(Error::Logic(format!(
+                    "{} never goes high in the same press as the sub-circuits before it",
+                    subcircuit.output
+                )));
             }
-        });
+            let lcm = modulus / g * m;
+            rem = (rem + (r - rem) / g * p % (m / g) * modulus).rem_euclid(lcm);
+            modulus = lcm;
+        }
+
+        let first = self.subcircuits.iter().map(|s| s.first).max().unwrap_or(1) as i128;
+        let mut presses = rem;
+        while presses < first {
+            presses += modulus;
+        }
+        usize::try_from(presses)
+            .map_err(|_| Error::Logic(format!("{} presses do not fit", presses)))
     }
+}
 
-    high * low
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - a / b * y)
+    }
 }
 
-pub fn part2(input: &Input) -> usize {
-    let mut machine = input.machine.clone();
+impl Input {
+    // Splits the circuit feeding `rx` into sub-circuits and simulates each of
+    // them alone, reporting which assumption failed if that isn't possible.
+    pub fn analyze(&self) -> Result<Analysis, Error> {
+        let machine = &self.machine;
+        let mut inputs: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (from, links) in &machine.links {
+            for to in links {
+                inputs.entry(to).or_default().push(from);
+            }
+        }
+        for links in inputs.values_mut() {
+            links.sort_unstable();
+        }
+        let kind = |name: &str| machine.modules.get(name).map(|module| module.kind());
+
+        let sink = match inputs.get("rx").map(Vec::as_slice) {
+            Some([sink]) => *sink,
+            Some(parents) => {
+                return Err(Error::Logic(format!(
+                    "rx has {} inputs, expected one",
+                    parents.len()
+                )))
+            }
+            None => return Err(Error::Logic("nothing sends pulses to rx".to_string())),
+        };
+        if kind(sink) != Some(Kind::Conjunction) {
+            return Err(Error::Logic(format!(
+                "{} feeding rx is not a conjunction",
+                sink
+            )));
+        }
 
-    let goal = "rx".to_string();
-    let parent = machine
-        .links
-        .iter()
-        .find_map(|(name, link)| {
-            if link.contains(&goal) {
-                Some(name.clone())
-            } else {
-                None
+        let broadcaster = machine
+            .links
+            .get("broadcaster")
+            .ok_or_else(no_broadcaster)?;
+
+        let mut subcircuits = Vec::new();
+        for &output in &inputs[sink] {
+            let mut modules = vec![output];
+            let mut stack = vec![output];
+            while let Some(curr) = stack.pop() {
+                for &from in inputs.get(curr).into_iter().flatten() {
+                    if from != "broadcaster" && from != "button" && !modules.contains(&from) {
+                        modules.push(from);
+                        stack.push(from);
+                    }
+                }
             }
-        })
-        .unwrap();
+            modules.sort_unstable();
 
-    let ancestors: Vec<_> = machine
-        .links
-        .iter()
-        .filter_map(|(name, link)| {
-            if link.contains(&parent) {
-                Some(name.clone())
-            } else {
-                None
+            for &module in &modules {
+                if kind(module).is_none() {
+                    return Err(Error::Logic(format!("{} is not a module", module)));
+                }
+                for to in &machine.links[module] {
+                    let inside = modules.contains(&to.as_str()) || (module == output && to == sink);
+                    if !inside {
+                        return Err(Error::Logic(format!(
+                            "{} in the sub-circuit of {} also feeds {}, so it is not independent",
+                            module, output, to
+                        )));
+                    }
+                }
+            }
+
+            let entries: Vec<&str> = broadcaster
+                .iter()
+                .map(String::as_str)
+                .filter(|entry| modules.contains(entry))
+                .collect();
+            if entries.is_empty() {
+                return Err(Error::Logic(format!(
+                    "the broadcaster does not feed the sub-circuit of {}",
+                    output
+                )));
             }
+
+            let counter = match entries[..] {
+                [entry] => detect_counter(machine, entry, &modules),
+                _ => None,
+            };
+            let (first, period) = simulate(machine, output, sink, &entries, &modules)?;
+            subcircuits.push(Subcircuit {
+                output: output.to_string(),
+                modules: modules.iter().map(|m| m.to_string()).collect(),
+                counter,
+                first,
+                period,
+                phase: first % period,
+            });
+        }
+
+        Ok(Analysis {
+            sink: sink.to_string(),
+            subcircuits,
         })
-        .collect();
+    }
+}
 
-    let mut history: HashMap<String, Option<usize>> =
-        ancestors.iter().map(|a| (a.clone(), None)).collect();
+fn detect_counter(machine: &Machine, entry: &str, modules: &[&str]) -> Option<Counter> {
+    let kind = |name: &str| machine.modules.get(name).map(|module| module.kind());
+    let mut bits = vec![entry.to_string()];
+    let mut hub: Option<&str> = None;
+    let mut target = 0;
+    loop {
+        let bit = bits.last().unwrap();
+        if kind(bit) != Some(Kind::FlipFlop) {
+            return None;
+        }
+        let mut next = None;
+        for to in &machine.links[bit] {
+            match kind(to) {
+                Some(Kind::FlipFlop) if next.is_none() => next = Some(to.clone()),
+                Some(Kind::Conjunction) if hub.is_none_or(|hub| hub == to) => {
+                    hub = Some(to);
+                    target |= 1 << (bits.len() - 1);
+                }
+                _ => return None,
+            }
+        }
+        match next {
+            Some(next) if !bits.contains(&next) => bits.push(next),
+            Some(_) => return None,
+            None => break,
+        }
+    }
+
+    // The hub resets every bit the target leaves clear.
+    let hub = hub?;
+    let resets = &machine.links[hub];
+    let complete = bits
+        .iter()
+        .enumerate()
+        .all(|(i, bit)| target & (1 << i) != 0 || resets.contains(bit));
+    if !complete || !modules.contains(&hub) {
+        return None;
+    }
+    Some(Counter {
+        bits,
+        hub: hub.to_string(),
+        target,
+    })
+}
+
+fn no_broadcaster() -> Error {
+    Error::Logic("circuit has no broadcaster".to_string())
+}
 
-    for i in 1.. {
-        machine.run(|from, pulse, _to: &String| {
-            if ancestors.contains(from) && pulse == Pulse::High {
-                history.get_mut(from).unwrap().replace(i);
+const MAX_PRESSES: usize = 1 << 20;
+
+// Returns the first press in which the output goes high, and the period
+// after which it does so again, which must hold for two more periods.
+fn simulate(
+    machine: &Machine,
+    output: &str,
+    sink: &str,
+    entries: &[&str],
+    modules: &[&str],
+) -> Result<(usize, usize), Error> {
+    let mut part = Machine {
+        modules: modules
+            .iter()
+            .map(|&name| (name.to_string(), machine.modules[name].clone()))
+            .collect(),
+        links: modules
+            .iter()
+            .map(|&name| (name.to_string(), machine.links[name].clone()))
+            .collect(),
+        queue: VecDeque::new(),
+    };
+    let broadcaster = machine
+        .modules
+        .get("broadcaster")
+        .ok_or_else(no_broadcaster)?;
+    part.modules
+        .insert("broadcaster".to_string(), broadcaster.clone());
+    part.links.insert(
+        "broadcaster".to_string(),
+        entries.iter().map(|e| e.to_string()).collect(),
+    );
+
+    let mut highs = Vec::new();
+    for press in 1..=MAX_PRESSES {
+        let mut high = false;
+        let mut level = Pulse::Low;
+        part.run(|from, pulse, to| {
+            if from == output && to == sink {
+                high |= pulse == Pulse::High;
+                level = pulse;
             }
         });
+        if high {
+            if level == Pulse::High {
+                return Err(Error::Logic(format!(
+                    "{} stays high after press {}",
+                    output, press
+                )));
+            }
+            highs.push(press);
+            if highs.len() == 4 {
+                break;
+            }
+        }
+    }
 
-        if history.values().all(|list| list.is_some()) {
-            return history.values().flatten().copied().fold(1, lcm);
+    match highs[..] {
+        [] => Err(Error::Logic(format!(
+            "{} never goes high within {} presses",
+            output, MAX_PRESSES
+        ))),
+        [first, second, third, fourth] => {
+            let period = second - first;
+            if third - second != period || fourth - third != period {
+                return Err(Error::Logic(format!(
+                    "{} goes high in presses {}, {}, {} and {}, which is not periodic",
+                    output, first, second, third, fourth
+                )));
+            }
+            Ok((first, period))
         }
+        _ => Err(Error::Logic(format!(
+            "{} only goes high in presses {:?} within {} presses",
+            output, highs, MAX_PRESSES
+        ))),
     }
-    unreachable!()
+}
+
+pub fn part1(input: &Input) -> usize {
+    let mut high = 0;
+    let mut low = 0;
+
+    let mut machine = input.machine.clone();
+    for _ in 1..=1000 {
+        machine.run(|_from, pulse, _to| match pulse {
+            Pulse::High => high += 1,
+            Pulse::Low => low += 1,
+        });
+    }
+
+    high * low
+}
+
+pub fn part2(input: &Input) -> usize {
+    input.analyze().unwrap().presses().unwrap()
 }
@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 
+use crate::animation::{Animation, Swatch};
 use ndarray::Array2;
 
 #[derive(Debug)]
@@ -21,7 +22,13 @@ fn manhatten_distance(lhs: &(isize, isize), rhs: &(isize, isize)) -> usize {
     lhs.0.abs_diff(rhs.0) + lhs.1.abs_diff(rhs.1)
 }
 
-fn flood(map: &Array2<char>, max_steps: usize) -> usize {
+// Calls `observe` with every step, the plots first reached in that step and
+// all plots reached so far.
+fn flood_with(
+    map: &Array2<char>,
+    max_steps: usize,
+    mut observe: impl FnMut(usize, &[(isize, isize)], &HashSet<(isize, isize)>),
+) -> usize {
     let (height, width) = map.dim();
     let rocks: HashSet<_> = map
         .indexed_iter()
@@ -35,13 +42,11 @@ fn flood(map: &Array2<char>, max_steps: usize) -> usize {
 
     let mut visited = HashSet::new();
     let mut check = vec![start];
-    for _ in 0..=max_steps {
-        let from = check.clone();
-        check.clear();
+    for step in 0..=max_steps {
+        let mut from = std::mem::take(&mut check);
+        from.retain(|&coord| visited.insert(coord));
+        observe(step, &from, &visited);
         for (y, x) in from {
-            if !visited.insert((y, x)) {
-                continue;
-            }
             for to in [(y - 1, x), (y + 1, x), (y, x - 1), (y, x + 1)] {
                 let normalized = (
                     to.0.rem_euclid(height as isize) as usize,
@@ -61,6 +66,72 @@ fn flood(map: &Array2<char>, max_steps: usize) -> usize {
         .count()
 }
 
+fn flood(map: &Array2<char>, max_steps: usize) -> usize {
+    flood_with(map, max_steps, |_, _, _| {})
+}
+
+impl Input {
+    // Draws the flood over a window of `(2 * radius + 1)^2` garden tiles
+    // centred on the original one, one frame per step.
+    pub fn animate(&self, max_steps: usize, radius: usize) -> Animation {
+        let palette = vec![
+            Swatch {
+                color: [40, 120, 40],
+                glyph: '.',
+            },
+            Swatch {
+                color: [96, 96, 96],
+                glyph: '#',
+            },
+            Swatch {
+                color: [255, 255, 255],
+                glyph: 'S',
+            },
+            Swatch {
+                color: [80, 160, 255],
+                glyph: 'O',
+            },
+            Swatch {
+                color: [255, 40, 40],
+                glyph: '@',
+            },
+        ];
+        let (height, width) = self.map.dim();
+        let tiles = 2 * radius + 1;
+        let (frame_height, frame_width) = (height * tiles, width * tiles);
+        let background: Vec<u8> = (0..frame_height)
+            .flat_map(|y| (0..frame_width).map(move |x| (y, x)))
+            .map(|(y, x)| match self.map[(y % height, x % width)] {
+                '#' => 1,
+                'S' if y / height == radius && x / width == radius => 2,
+                _ => 0,
+            })
+            .collect();
+
+        let offset = (radius * height) as isize;
+        let window = |(y, x): (isize, isize)| {
+            let (y, x) = (y + offset, x + (radius * width) as isize);
+            if (0..frame_height as isize).contains(&y) && (0..frame_width as isize).contains(&x) {
+                Some(y as usize * frame_width + x as usize)
+            } else {
+                None
+            }
+        };
+        let mut animation = Animation::new(frame_width, frame_height, palette);
+        flood_with(&self.map, max_steps, |_, frontier, visited| {
+            let mut frame = background.clone();
+            for i in visited.iter().filter_map(|&coord| window(coord)) {
+                frame[i] = 3;
+            }
+            for i in frontier.iter().filter_map(|&coord| window(coord)) {
+                frame[i] = 4;
+            }
+            animation.push(frame);
+        });
+        animation
+    }
+}
+
 pub fn part1(input: &Input) -> usize {
     flood(&input.map, 64)
 }
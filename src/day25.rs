@@ -95,7 +95,7 @@ struct Edge(String, String);
 impl Edge {
     fn new(from: &str, to: &str) -> Self {
         // In order to have a stable comparison between edges, we sort the to/from vertices.
-        let (to, from) = match from.cmp(&to) {
+        let (to, from) = match from.cmp(to) {
             Ordering::Greater => (to.to_string(), from.to_string()),
             Ordering::Less => (from.to_string(), to.to_string()),
             Ordering::Equal => panic!("an edge cannot point to itself: {from}"),
@@ -127,7 +127,7 @@ impl History {
 
     fn top(&self, amount: usize) -> Vec<(&Edge, &usize)> {
         let mut edges: Vec<_> = self.hist.iter().collect();
-        edges.sort_by(|lhs, rhs| rhs.1.cmp(&lhs.1));
+        edges.sort_by(|lhs, rhs| rhs.1.cmp(lhs.1));
         edges.truncate(amount);
         edges
     }
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::Read;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("IO error")]
+    Io(#[from] std::io::Error),
+    #[error("logic error: {0}")]
+    Logic(String),
+}
+
+pub fn parse_input<I>(day: &str) -> Result<I, Error>
+where
+    for<'s> I: Sized + From<&'s str>,
+{
+    let filename = format!("input/{}.txt", day);
+    let mut file = File::open(filename)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(content.as_str().into())
+}
+
+pub mod animation;
+pub mod cycle;
+pub mod image;
+
+pub mod day01;
+pub mod day02;
+pub mod day03;
+pub mod day04;
+pub mod day05;
+pub mod day06;
+pub mod day07;
+pub mod day08;
+pub mod day09;
+pub mod day10;
+pub mod day11;
+pub mod day12;
+pub mod day13;
+pub mod day14;
+pub mod day15;
+pub mod day16;
+pub mod day17;
+pub mod day18;
+pub mod day19;
+pub mod day20;
+pub mod day21;
+pub mod day22;
+pub mod day23;
+pub mod day24;
+pub mod day25;
+pub mod nonogram;
@@ -1,28 +1,11 @@
-use std::fs::File;
-use std::io::Read;
-
-#[derive(thiserror::Error, Debug)]
-pub enum Error {
-    #[error("IO error")]
-    Io(#[from] std::io::Error),
-    #[error("logic error: {0}")]
-    Logic(String),
-}
-
-pub fn parse_input<I>(day: &str) -> Result<I, Error>
-where
-    for<'s> I: Sized + From<&'s str>,
-{
-    let filename = format!("input/{}.txt", day);
-    let mut file = File::open(filename)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-    Ok(content.as_str().into())
-}
+use aoc2023::animation::Animation;
+use aoc2023::nonogram::{Puzzle, Solution};
+use aoc2023::parse_input;
+use std::io::Write;
 
 macro_rules! aoc_impl {
     ($($day:ident),*) => {
-        $(mod $day;)*
+        use aoc2023::{$($day),*};
 
         fn run_day(day: usize) {
             assert!((1..=25).contains(&day));
@@ -56,8 +39,114 @@ aoc_impl!(
     day14, day15, day16, day17, day18, day19, day20, day21, day22, day23, day24, day25
 );
 
+fn solve_nonogram(filename: &str) {
+    let content = std::fs::read_to_string(filename).expect("Failed to read clue file");
+    let puzzle: Puzzle = content.parse().expect("Failed to parse clue file");
+    match puzzle.solve().expect("Failed to solve puzzle") {
+        Solution::Unique(picture) => print!("{}", picture),
+        Solution::Multiple(a, b) => {
+            println!("multiple solutions, e.g.");
+            print!("{}", a);
+            println!();
+            print!("{}", b);
+        }
+        Solution::Unsolvable => println!("no solution"),
+    }
+}
+
+fn animate(day: &str, filename: &str) {
+    let animation: Animation = match day {
+        "16" => {
+            let input: day16::Input = parse_input("day16").expect("Failed to parse input");
+            let optics = day16::Optics::default();
+            input.animate(&optics, (0, 0), day16::Direction::Right)
+        }
+        "21" => {
+            let input: day21::Input = parse_input("day21").expect("Failed to parse input");
+            input.animate(64, 1)
+        }
+        _ => panic!("No animation for day {}", day),
+    };
+    let mut file = std::fs::File::create(filename).expect("Failed to create output file");
+    if filename.ends_with(".gif") {
+        animation.write_gif(&mut file)
+    } else {
+        animation.write_text(&mut file)
+    }
+    .expect("Failed to write animation");
+}
+
+fn render_dig_plan(part: &str, filename: &str) {
+    let input: day18::Input = parse_input("day18").expect("Failed to parse input");
+    let steps = match part {
+        "1" => input.steps(),
+        "2" => input.hex_steps(),
+        _ => panic!("No dig plan for part {}", part),
+    };
+    let image = day18::render(steps, 1000).expect("Failed to render dig plan");
+    let mut file = std::fs::File::create(filename).expect("Failed to create output file");
+    if filename.ends_with(".png") {
+        image.write_png(&mut file)
+    } else {
+        image.write_ppm(&mut file)
+    }
+    .expect("Failed to write image");
+}
+
+fn optimize_workflows() {
+    let input: day19::Input = parse_input("day19").expect("Failed to parse input");
+    for lint in input.lint(1..4001) {
+        eprintln!("{}", lint);
+    }
+    let optimized = input
+        .optimize(1..4001)
+        .expect("Failed to optimize workflows");
+    print!("{}", optimized);
+}
+
+fn trace_pulses(presses: &str, filename: &str) {
+    let input: day20::Input = parse_input("day20").expect("Failed to parse input");
+    let trace = input.trace(presses.parse().expect("Invalid number of presses"));
+    let mut file = std::fs::File::create(filename).expect("Failed to create output file");
+    if filename.ends_with(".vcd") {
+        trace.write_vcd(&mut file)
+    } else {
+        write!(file, "{}", trace)
+    }
+    .expect("Failed to write trace");
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("nonogram") {
+        solve_nonogram(args.get(2).expect("Missing clue file"));
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("animate") {
+        animate(
+            args.get(2).expect("Missing day"),
+            args.get(3).expect("Missing output file"),
+        );
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("dig") {
+        render_dig_plan(
+            args.get(2).expect("Missing part"),
+            args.get(3).expect("Missing output file"),
+        );
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("optimize") {
+        optimize_workflows();
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("trace") {
+        trace_pulses(
+            args.get(2).expect("Missing number of presses"),
+            args.get(3).expect("Missing output file"),
+        );
+        return;
+    }
     let day = args.get(1).map(|n| n.parse().unwrap());
     run(day);
 }
@@ -0,0 +1,177 @@
+use crate::day12::Spring;
+use crate::Error;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+// Clue files list the row clues under a `rows:` header followed by the column
+// clues under a `columns:` header, one line of comma separated group lengths
+// each, using `0` for an empty line. Blank lines and `#` comments are ignored.
+#[derive(Debug)]
+pub struct Puzzle {
+    rows: Vec<Vec<usize>>,
+    cols: Vec<Vec<usize>>,
+}
+
+impl FromStr for Puzzle {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rows = Vec::new();
+        let mut cols = Vec::new();
+        let mut section = None;
+        for (n, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line {
+                "rows:" => section = Some(&mut rows),
+                "columns:" => section = Some(&mut cols),
+                _ => {
+                    let clues = section.as_mut().ok_or_else(|| {
+                        Error::Logic(format!("line {}: clue outside of a section", n + 1))
+                    })?;
+                    let groups = line
+                        .split(',')
+                        .map(|g| g.trim().parse::<usize>())
+                        .filter(|g| g != &Ok(0))
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| Error::Logic(format!("line {}: invalid clue", n + 1)))?;
+                    clues.push(groups);
+                }
+            }
+        }
+
+        if rows.is_empty() || cols.is_empty() {
+            return Err(Error::Logic(
+                "puzzle needs both rows and columns".to_string(),
+            ));
+        }
+        Ok(Self::new(rows, cols))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Picture {
+    cells: Vec<Vec<char>>,
+}
+
+impl Picture {
+    pub fn cells(&self) -> &[Vec<char>] {
+        &self.cells
+    }
+}
+
+impl fmt::Display for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.cells {
+            writeln!(f, "{}", row.iter().collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Solution {
+    Unique(Picture),
+    Multiple(Picture, Picture),
+    Unsolvable,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Line {
+    Row(usize),
+    Col(usize),
+}
+
+impl Puzzle {
+    pub fn new(rows: Vec<Vec<usize>>, cols: Vec<Vec<usize>>) -> Self {
+        Self { rows, cols }
+    }
+
+    pub fn solve(&self) -> Result<Solution, Error> {
+        let grid = vec![vec!['?'; self.cols.len()]; self.rows.len()];
+        let mut solutions = Vec::new();
+        self.search(grid, &mut solutions)?;
+
+        let mut solutions = solutions.into_iter();
+        Ok(match (solutions.next(), solutions.next()) {
+            (Some(a), Some(b)) => Solution::Multiple(a, b),
+            (Some(a), None) => Solution::Unique(a),
+            _ => Solution::Unsolvable,
+        })
+    }
+
+    // Propagates line constraints until nothing changes, then guesses on the
+    // first unknown cell. Stops as soon as two solutions are known.
+    fn search(&self, mut grid: Vec<Vec<char>>, solutions: &mut Vec<Picture>) -> Result<(), Error> {
+        if !self.propagate(&mut grid)? {
+            return Ok(());
+        }
+
+        let unknown = grid
+            .iter()
+            .enumerate()
+            .find_map(|(r, row)| row.iter().position(|&c| c == '?').map(|c| (r, c)));
+        match unknown {
+            None => solutions.push(Picture { cells: grid }),
+            Some((r, c)) => {
+                for guess in ['#', '.'] {
+                    if solutions.len() >= 2 {
+                        break;
+                    }
+                    let mut grid = grid.clone();
+                    grid[r][c] = guess;
+                    self.search(grid, solutions)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn propagate(&self, grid: &mut [Vec<char>]) -> Result<bool, Error> {
+        let mut queue: VecDeque<_> = (0..self.rows.len())
+            .map(Line::Row)
+            .chain((0..self.cols.len()).map(Line::Col))
+            .collect();
+        let mut queued = (vec![true; self.rows.len()], vec![true; self.cols.len()]);
+
+        while let Some(line) = queue.pop_front() {
+            let (cells, groups): (String, _) = match line {
+                Line::Row(r) => {
+                    queued.0[r] = false;
+                    (grid[r].iter().collect(), &self.rows[r])
+                }
+                Line::Col(c) => {
+                    queued.1[c] = false;
+                    (grid.iter().map(|row| row[c]).collect(), &self.cols[c])
+                }
+            };
+
+            let spring = Spring::new(&cells, groups);
+            if spring.arrangements()? == 0 {
+                return Ok(false);
+            }
+            for (i, value) in spring.forced()? {
+                match line {
+                    Line::Row(r) => {
+                        grid[r][i] = value;
+                        if !queued.1[i] {
+                            queued.1[i] = true;
+                            queue.push_back(Line::Col(i));
+                        }
+                    }
+                    Line::Col(c) => {
+                        grid[i][c] = value;
+                        if !queued.0[i] {
+                            queued.0[i] = true;
+                            queue.push_back(Line::Row(i));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}